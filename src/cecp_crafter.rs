@@ -1,52 +1,76 @@
+use std::marker::PhantomData;
+use std::ops::Deref;
 use std::ptr::{null, null_mut};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use itertools::Itertools;
 use rayon::prelude::*;
-use rayon::{max_num_threads, current_thread_index};
+use rayon::max_num_threads;
 use crate::{cecp::ECPOpt, CintType, CINTR2CDATA};
 use crate::cecp::*;
 use crate::cecp_wrapper::*;
 use crate::cint;
 use crate::utilities::*;
+use crate::cint_optimizer::{ECPOptimizer, MoleculeDigest, SharedOptimizerCache};
+use crate::cint_parallel::{Parallelism, ThreadBuffers};
+use crate::cint_schedule::{shell_tuple_cost, schedule_by_cost};
 
-#[derive(Clone)]
 pub struct ECPData {
-    c_opt: *mut ECPOpt,
+    c_opt: ECPOptimizer,
     c_natm: i32,
     c_nbas: i32,
     cint_type: CintType,
     c_atm: Vec<i32>,
     c_bas: Vec<i32>,
     c_env: Vec<f64>,
+    opt_cache: SharedOptimizerCache,
+    parallelism: Parallelism,
 }
 
-unsafe impl Send for ECPData {}
-unsafe impl Sync for ECPData {}
+/// Copy of molecule data. Optimizer set by `optimizer::<T>` is not copied, while optimizer cache is shared.
+impl Clone for ECPData {
+    fn clone(&self) -> Self {
+        ECPData {
+            c_opt: ECPOptimizer::null(),
+            c_natm: self.c_natm,
+            c_nbas: self.c_nbas,
+            cint_type: self.cint_type,
+            c_atm: self.c_atm.clone(),
+            c_bas: self.c_bas.clone(),
+            c_env: self.c_env.clone(),
+            opt_cache: self.opt_cache.clone(),
+            parallelism: self.parallelism.clone(),
+        }
+    }
+}
 
 impl ECPData {
 
     pub fn new() -> ECPData {
         ECPData {
-            c_opt: null_mut(),
+            c_opt: ECPOptimizer::null(),
             c_natm: 0,
             c_nbas: 0,
             cint_type: CintType::Spheric,
             c_atm: Vec::new(),
             c_bas: Vec::new(),
             c_env: Vec::new(),
+            opt_cache: SharedOptimizerCache::default(),
+            parallelism: Parallelism::default(),
         }
     }
 
     pub fn from_cint_data(cint_data: &CINTR2CDATA) -> ECPData {
         // initialize
         let mut ecp_data = ECPData {
-            c_opt: null_mut(),
+            c_opt: ECPOptimizer::null(),
             c_natm: cint_data.c_natm,
             c_nbas: cint_data.c_nbas,
             cint_type: cint_data.cint_type,
             c_atm: cint_data.c_atm.clone(),
             c_bas: cint_data.c_bas.clone(),
             c_env: cint_data.c_env.clone(),
+            opt_cache: cint_data.opt_cache.clone(),
+            parallelism: cint_data.parallelism.clone(),
         };
         // concate ECP data
         ecp_data.c_bas.append(&mut cint_data.c_ecp.clone());
@@ -57,21 +81,14 @@ impl ECPData {
     }
 
     pub fn optimizer_destruct(&mut self) {
-        unsafe { ECPdel_optimizer(&mut self.c_opt); }
+        self.c_opt = ECPOptimizer::null();
     }
 
     pub fn optimizer<T> (&mut self)
     where
         T: ECPIntegrator
     {
-        self.optimizer_destruct();
-        unsafe {
-            T::optimizer(
-                &mut self.c_opt,
-                self.c_atm.as_ptr(), self.c_natm,
-                self.c_bas.as_ptr(), self.c_nbas,
-                self.c_env.as_ptr());
-        }
+        self.c_opt = ECPOptimizer::new::<T>(&self.c_atm, self.c_natm, &self.c_bas, self.c_nbas, &self.c_env);
     }
 
     /* #region cgto size */
@@ -203,7 +220,7 @@ impl ECPData {
     /// If the shell slice is not known to you currently (molecule information has passed into
     /// `ECPData`), just pass empty `shls_slice = vec![]`, then it should give the maximum
     /// cache size for this molecule/intor.
-    pub fn size_of_cache<T> (&mut self, shls_slice: &[[i32; 2]]) -> usize
+    pub fn size_of_cache<T> (&self, shls_slice: &[[i32; 2]]) -> usize
     where
         T: ECPIntegrator
     {
@@ -259,6 +276,17 @@ impl ECPData {
     ///     using `vec![]` should also works, which lets libcint manages cache and efficiency decreases.
     ///     See Also [`Self::max_cache_size`] for guide of properly allocate cache.
     pub unsafe fn integral_block<T> (&self, out: &mut [f64], shls: &[i32], shape: &[i32], cache: &mut [f64])
+    where
+        T: ECPIntegrator
+    {
+        self.integral_block_with_optimizer::<T>(out.as_mut_ptr(), shls, shape, cache, self.c_opt.as_ptr());
+    }
+
+    /// Smallest unit of ECP integral function, with optimizer given by caller.
+    ///
+    /// Optimizer should be generated for the same integrator `T` (or be null).
+    /// Output is given by raw pointer; see also [`CINTR2CDATA::integral_block_with_optimizer`].
+    pub unsafe fn integral_block_with_optimizer<T> (&self, out: *mut f64, shls: &[i32], shape: &[i32], cache: &mut [f64], c_opt: *const ECPOpt)
     where
         T: ECPIntegrator
     {
@@ -273,17 +301,17 @@ impl ECPData {
         match self.cint_type {
             CintType::Spheric => unsafe {
                 T::integral_sph(
-                    out.as_mut_ptr(), shape_ptr, shls.as_ptr(),
+                    out, shape_ptr, shls.as_ptr(),
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
-                    self.c_env.as_ptr(), self.c_opt, cache_ptr)
+                    self.c_env.as_ptr(), c_opt, cache_ptr)
                 },
             CintType::Cartesian => unsafe {
                 T::integral_cart(
-                    out.as_mut_ptr(), shape_ptr, shls.as_ptr(),
+                    out, shape_ptr, shls.as_ptr(),
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
-                    self.c_env.as_ptr(), self.c_opt, cache_ptr)
+                    self.c_env.as_ptr(), c_opt, cache_ptr)
                 },
             CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
         };
@@ -293,9 +321,88 @@ impl ECPData {
     /// 
     /// This function a low-level API, which is not intended to be called by user.
     /// This function only works for f-contiguous integral (PySCF convention).
-    pub fn integral_s1_inplace<T> (&mut self, out: &mut Vec<f64>, shl_slices: &[[i32; 2]])
+    pub fn integral_s1_inplace<T> (&self, out: &mut [f64], shl_slices: &[[i32; 2]])
+    where
+        T: ECPIntegrator
+    {
+        ECPEvalContext::<T>::new(self).integral_s1_inplace(out, shl_slices);
+    }
+
+    pub fn integral_s1<T> (&self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
     where
         T: ECPIntegrator
+    {
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices,
+            None => {
+                let nbas = self.c_env[AS_ECPBAS_OFFSET as usize] as i32;
+                &vec![[0, nbas]; 2]
+            },
+        };
+        let mut out_shape = self.cgto_shape::<T>(&shl_slices);
+        if T::n_comp() > 1 { out_shape.push(T::n_comp()); }
+        let out_size = out_shape.iter().product::<usize>();
+        let mut out = vec![0.; out_size];
+        self.integral_s1_inplace::<T>(&mut out, &shl_slices);
+        return (out, out_shape);
+    }
+
+}
+
+/// Evaluation context of one ECP integral call.
+///
+/// Optimizer of ECP integrator `T` is owned by this context instead of `ECPData`, and taken from optimizer
+/// cache (shared with `CINTR2CDATA` that `ECPData` is created from). See also [`crate::cint_context::CINTEvalContext`].
+pub struct ECPEvalContext<'a, T>
+where
+    T: ECPIntegrator
+{
+    ecp_data: &'a ECPData,
+    optimizer: Arc<ECPOptimizer>,
+    _integrator: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> ECPEvalContext<'a, T>
+where
+    T: ECPIntegrator
+{
+    pub fn new(ecp_data: &'a ECPData) -> Self {
+        let digest = MoleculeDigest::new(&ecp_data.c_atm, &ecp_data.c_bas, &ecp_data.c_env);
+        let optimizer = ecp_data.opt_cache.lock().unwrap().get_ecp_with_digest::<T>(
+            &digest,
+            &ecp_data.c_atm, ecp_data.c_natm,
+            &ecp_data.c_bas, ecp_data.c_nbas,
+            &ecp_data.c_env);
+        ECPEvalContext { ecp_data, optimizer, _integrator: PhantomData }
+    }
+
+    /// Smallest unit of ECP integral function, with optimizer of this context.
+    ///
+    /// See also [`ECPData::integral_block`].
+    ///
+    /// # Safety
+    ///
+    /// `out` should hold the block of `shls` in buffer of `shape` (including all components), and `cache`
+    /// should not be smaller than `size_of_cache`; ECP functions do not check either.
+    pub unsafe fn integral_block(&self, out: &mut [f64], shls: &[i32], shape: &[i32], cache: &mut [f64]) {
+        self.ecp_data.integral_block_with_optimizer::<T>(out.as_mut_ptr(), shls, shape, cache, self.optimizer.as_ptr());
+    }
+
+    /// Same to [`Self::integral_block`], but output is given by raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// Same to [`Self::integral_block`]; in addition, `out` should be valid for writes of the whole block
+    /// (`DisjointMut::ptr_at` with extent of the block), and no other thread may access the block meanwhile.
+    pub unsafe fn integral_block_ptr(&self, out: *mut f64, shls: &[i32], shape: &[i32], cache: &mut [f64]) {
+        self.ecp_data.integral_block_with_optimizer::<T>(out, shls, shape, cache, self.optimizer.as_ptr());
+    }
+
+    /// Main integral engine for s1 symmetry.
+    /// 
+    /// This function a low-level API, which is not intended to be called by user.
+    /// This function only works for f-contiguous integral (PySCF convention).
+    pub fn integral_s1_inplace (&self, out: &mut [f64], shl_slices: &[[i32; 2]])
     {
         /* #region 1. dimension definition and sanity check */
 
@@ -306,37 +413,46 @@ impl ECPData {
         let cgto_shape_i32 = cgto_shape.iter().map(|&v| v as i32).collect::<Vec<i32>>();
         let index_shape = shl_slices.iter().map(|[shl_start, shl_stop]| (shl_stop - shl_start) as usize).collect_vec();
         let cgto_locs_rel = self.cgto_loc_slices_relative(shl_slices);
+        let out_size = cgto_shape.iter().product::<usize>() * n_comp;
+        if out.len() < out_size {
+            panic!("size of output {} is smaller than required {out_size}", out.len());
+        }
 
         /* #endregion */
 
         /* #region 2. preparation for integral engine */
 
-        // optimizer (make integral faster)
-        self.optimizer::<T>();
-
         // cache: thread-local
         let cache_size = self.size_of_cache::<T>(shl_slices);
-        let thread_cache = (0..rayon::current_num_threads()).map(|n| {Mutex::new(vec![0.; cache_size])}).collect_vec();
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
 
-        // out: enable mut vector by passing immut slice
-        let out_const_slice = out.as_slice();
+        // out: shared by threads, each parallel task writes its own (disjoint) blocks
+        let out = DisjointMut::new(out);
 
         // reverse iteration for f-contiguous
         let index_shape_rev = index_shape.into_iter().rev().collect_vec();
         let shl_slices_rev = shl_slices.iter().rev().collect_vec();
         let cgto_locs_rel_rev = cgto_locs_rel.iter().rev().collect_vec();
         let cgto_shape_rev = cgto_shape.iter().rev().collect_vec();
+        let cgto_width_rev = |k: usize, idx: usize| cgto_locs_rel_rev[k][idx + 1] - cgto_locs_rel_rev[k][idx];
+        let out_shape = cgto_shape.iter().copied().chain([n_comp]).collect_vec();
 
         /* #endregion */
 
         /* #region 3. parallel integral generation */
 
-        // Following code of parallel is not fearless.
-        // Variable `out` will be written in parallel, which should be considered racing,
-        // when calling `self.integral_block::<T>`,
-        // and racing would not actually happen if I am careful.
+        // Each task (shell pair) writes its own block; blocks of different tasks never overlap,
+        // so writing through `DisjointMut` is free of data racing.
+
+        // expensive shell pairs are dispatched first
+        let task_costs = (0..(index_shape_rev[0] * index_shape_rev[1])).map(|idx_01| {
+            let shl_0 = (idx_01 / index_shape_rev[1]) as i32 + shl_slices_rev[0][0];
+            let shl_1 = (idx_01 % index_shape_rev[1]) as i32 + shl_slices_rev[1][0];
+            shell_tuple_cost(&self.c_bas, &[shl_1, shl_0])
+        }).collect_vec();
+        let task_order = schedule_by_cost(&task_costs);
 
-        (0..(index_shape_rev[0] * index_shape_rev[1])).into_par_iter().for_each(|idx_01| {
+        self.parallelism.for_each_ordered(&task_order, |idx_01| {
             let idx_0 = idx_01 / index_shape_rev[1];
             let idx_1 = idx_01 % index_shape_rev[1];
             let shl_0 = idx_0 as i32 + shl_slices_rev[0][0];
@@ -344,55 +460,38 @@ impl ECPData {
             let cgto_0 = cgto_locs_rel_rev[0][idx_0];
             let cgto_1 = cgto_locs_rel_rev[1][idx_1];
             
-            let thread_index = current_thread_index().unwrap_or(0);
-            let mut cache = thread_cache[thread_index].lock().unwrap();
+            let mut cache = thread_cache.get();
             
             let shls = [shl_1, shl_0];
             let offset = cgto_1 + cgto_shape_rev[1] * cgto_0;
+            let extent = block_extent(&[cgto_width_rev(1, idx_1), cgto_width_rev(0, idx_0), n_comp], &out_shape);
 
-            unsafe {
-                let out_with_offset = cast_mut_slice(&out_const_slice[offset..]);
-                self.integral_block::<T>(out_with_offset, &shls, &cgto_shape_i32, &mut cache);
-            }
+            unsafe { self.integral_block_ptr(out.ptr_at(offset, extent), &shls, &cgto_shape_i32, &mut cache); }
         });
         /* #endregion */
-
-        /* #region 4. cleanup */
-        self.optimizer_destruct();
-        /* #endregion */
     }
+}
 
-    pub fn integral_s1<T> (&mut self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
-    where
-        T: ECPIntegrator
-    {
-        let shl_slices = match shl_slices {
-            Some(shl_slices) => shl_slices,
-            None => {
-                let nbas = self.c_env[AS_ECPBAS_OFFSET as usize] as i32;
-                &vec![[0, nbas]; 2]
-            },
-        };
-        let mut out_shape = self.cgto_shape::<T>(&shl_slices);
-        if T::n_comp() > 1 { out_shape.push(T::n_comp()); }
-        let out_size = out_shape.iter().product::<usize>();
-        let mut out = Vec::<f64>::with_capacity(out_size);
-        unsafe { out.set_len(out_size) };
-        self.integral_s1_inplace::<T>(&mut out, &shl_slices);
-        return (out, out_shape);
+impl<T> Deref for ECPEvalContext<'_, T>
+where
+    T: ECPIntegrator
+{
+    type Target = ECPData;
+    fn deref(&self) -> &Self::Target {
+        self.ecp_data
     }
-
 }
 
+
 impl CINTR2CDATA {
-    pub fn integral_ecp_s1<T> (&mut self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
+    pub fn integral_ecp_s1<T> (&self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
     where
         T: ECPIntegrator
     {
         if self.c_necp == 0 {
             panic!("No ECP data found in the molecule.");
         }
-        let mut ecp_data = ECPData::from_cint_data(&self);
+        let ecp_data = ECPData::from_cint_data(self);
         ecp_data.integral_s1::<T>(shl_slices)
     }
 }
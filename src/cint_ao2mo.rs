@@ -0,0 +1,153 @@
+use crate::cint_wrapper::*;
+use crate::cint_parallel::ThreadBuffers;
+use crate::{CintType, CINTR2CDATA};
+
+/* #region packing and transformation helpers */
+
+/// Number of (packed) index pairs of two orbital sets; pairs are packed (`p <= q`) if coefficients are the same.
+pub(crate) fn npair(nmo_p: usize, nmo_q: usize, packed: bool) -> usize {
+    match packed {
+        true => nmo_p * (nmo_p + 1) / 2,
+        false => nmo_p * nmo_q,
+    }
+}
+
+/// Unpack lower-triangular packed symmetric matrix (`i + j * (j + 1) / 2` for `i <= j`) to full f-contiguous matrix.
+fn unpack_tril(packed: &[f64], n: usize, full: &mut [f64]) {
+    for j in 0..n {
+        for i in 0..(j + 1) {
+            let v = packed[i + j * (j + 1) / 2];
+            full[i + n * j] = v;
+            full[j + n * i] = v;
+        }
+    }
+}
+
+/// `C_p^T M C_q` of f-contiguous matrix `m` (ni × nj), giving (packed) pair vector of `(p, q)`.
+///
+/// `coeff_p` (ni × nmo_p) and `coeff_q` (nj × nmo_q) are f-contiguous; `tmp` has size of at least ni × nmo_q.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn transform_pair(m: &[f64], ni: usize, nj: usize, coeff_p: &[f64], nmo_p: usize, coeff_q: &[f64], nmo_q: usize, packed: bool, tmp: &mut [f64]) -> Vec<f64> {
+    // tmp = M C_q
+    for q in 0..nmo_q {
+        for k in 0..ni {
+            tmp[k + ni * q] = 0.;
+        }
+        for l in 0..nj {
+            let c = coeff_q[l + nj * q];
+            if c == 0. { continue; }
+            for k in 0..ni {
+                tmp[k + ni * q] += m[k + ni * l] * c;
+            }
+        }
+    }
+    // C_p^T tmp
+    let dot = |p: usize, q: usize| (0..ni).map(|k| coeff_p[k + ni * p] * tmp[k + ni * q]).sum::<f64>();
+    match packed {
+        true => (0..nmo_q).flat_map(|q| (0..(q + 1)).map(move |p| (p, q))).map(|(p, q)| dot(p, q)).collect(),
+        false => (0..nmo_q).flat_map(|q| (0..nmo_p).map(move |p| (p, q))).map(|(p, q)| dot(p, q)).collect(),
+    }
+}
+
+/* #endregion */
+
+impl CINTR2CDATA {
+    /// Number of MO (columns) of f-contiguous coefficient matrix with `nao` rows.
+    pub(crate) fn ao2mo_nmo(coeff: &[f64], nao: usize) -> usize {
+        if nao == 0 || !coeff.len().is_multiple_of(nao) {
+            panic!("size of coefficient matrix {} is not multiple of nao {nao}", coeff.len());
+        }
+        coeff.len() / nao
+    }
+
+    /// Half transformation of two-electron integrals: `(ij|kl)` to `(ij|rs)`, with AO pair `ij` packed (s2).
+    ///
+    /// * `coeff_k`, `coeff_l` -
+    ///     f-contiguous coefficient matrices of shape `(nao, nmo)`. If they are the same, MO pair `rs` is packed
+    ///     (`r <= s`, index `r + s * (s + 1) / 2`); otherwise MO pair is full (index `r + nmo_k * s`).
+    ///
+    /// Output is f-contiguous with shape `(nao * (nao + 1) / 2, npair_rs)`.
+    ///
+    /// AO integrals are evaluated shell-batch by shell-batch (one shell of `j` and all shells `i <= j`, with `kl`
+    /// packed), using 4-fold symmetry of `(ij|kl)`; the full AO tensor is never stored.
+    ///
+    /// Memory: output is held in full, `nao² nmo² / 4` f64 for packed pairs (not batched by a memory budget);
+    /// in addition, AO integrals of one shell batch take `nao³ d_j / 2` f64, with `d_j` size of the largest shell.
+    pub fn integral_ao2mo_half(&self, coeff_k: &[f64], coeff_l: &[f64]) -> (Vec<f64>, Vec<usize>) {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by AO-to-MO transformation");
+        }
+        let cgto_loc = self.cgto_loc();
+        let nao = *cgto_loc.last().unwrap();
+        let npair_ao = nao * (nao + 1) / 2;
+        let (nmo_k, nmo_l) = (Self::ao2mo_nmo(coeff_k, nao), Self::ao2mo_nmo(coeff_l, nao));
+        let packed_kl = coeff_k == coeff_l;
+        let npair_kl = npair(nmo_k, nmo_l, packed_kl);
+
+        // thread-local unpacked AO matrix and intermediate of transformation
+        let thread_full = ThreadBuffers::new(&self.parallelism, nao * nao, 0.);
+        let thread_tmp = ThreadBuffers::new(&self.parallelism, nao * nmo_l, 0.);
+
+        let mut out = vec![0.; npair_ao * npair_kl];
+        for shl_j in 0..self.c_nbas {
+            // (kl|ij) = (ij|kl), with kl packed; i of all shells up to shl_j
+            let shl_slices = [[0, self.c_nbas], [0, self.c_nbas], [0, shl_j + 1], [shl_j, shl_j + 1]];
+            let (eri, eri_shape) = self.integral_s2ij::<int2e>(Some(&shl_slices));
+            let (ni, dj) = (eri_shape[1], eri_shape[2]);
+            let j0 = cgto_loc[shl_j as usize];
+
+            // AO pairs (i, j) with i <= j in this batch
+            let pairs = (0..dj).flat_map(|jj| (0..(j0 + jj + 1)).map(move |i| (i, jj))).collect::<Vec<_>>();
+            let half = self.parallelism.map_collect(pairs.len(), |idx_pair| {
+                let (i, jj) = pairs[idx_pair];
+                let packed = &eri[npair_ao * (i + ni * jj)..npair_ao * (i + ni * jj + 1)];
+                let mut full = thread_full.get();
+                let mut tmp = thread_tmp.get();
+                unpack_tril(packed, nao, &mut full);
+                transform_pair(&full, nao, nao, coeff_k, nmo_k, coeff_l, nmo_l, packed_kl, &mut tmp)
+            });
+            for (&(i, jj), half) in pairs.iter().zip(half) {
+                let j = j0 + jj;
+                let ij = i + j * (j + 1) / 2;
+                half.iter().enumerate().for_each(|(rs, &v)| out[ij + npair_ao * rs] = v);
+            }
+        }
+        return (out, vec![npair_ao, npair_kl]);
+    }
+
+    /// Full AO-to-MO transformation of two-electron integrals: `(ij|kl)` to `(pq|rs)`.
+    ///
+    /// * `coeffs` -
+    ///     f-contiguous coefficient matrices `[C_i, C_j, C_k, C_l]` of shape `(nao, nmo)`; they could be general
+    ///     (different) or the same. MO pair `pq` (or `rs`) is packed (`p <= q`, index `p + q * (q + 1) / 2`)
+    ///     if `C_i == C_j` (or `C_k == C_l`); otherwise it is full (index `p + nmo_i * q`).
+    ///
+    /// Output is f-contiguous with shape `(npair_pq, npair_rs)`.
+    /// This is performed by half transformation ([`Self::integral_ao2mo_half`]), then transformation of `ij` for
+    /// each MO pair `rs`.
+    ///
+    /// Memory: the half-transformed tensor `(npair_ao, npair_rs)` is held in full together with output, so peak
+    /// memory is about `nao² nmo² / 4 + nmo⁴ / 4` f64 for packed pairs; it is not batched by a memory budget.
+    pub fn integral_ao2mo(&self, coeffs: [&[f64]; 4]) -> (Vec<f64>, Vec<usize>) {
+        let [coeff_i, coeff_j, coeff_k, coeff_l] = coeffs;
+        let nao = *self.cgto_loc().last().unwrap();
+        let (nmo_i, nmo_j) = (Self::ao2mo_nmo(coeff_i, nao), Self::ao2mo_nmo(coeff_j, nao));
+        let packed_ij = coeff_i == coeff_j;
+        let npair_ij = npair(nmo_i, nmo_j, packed_ij);
+
+        let (half, half_shape) = self.integral_ao2mo_half(coeff_k, coeff_l);
+        let [npair_ao, npair_kl] = [half_shape[0], half_shape[1]];
+
+        // each column (MO pair rs) of half-transformed integral is packed AO pair ij
+        let thread_full = ThreadBuffers::new(&self.parallelism, nao * nao, 0.);
+        let thread_tmp = ThreadBuffers::new(&self.parallelism, nao * nmo_j, 0.);
+        let out = self.parallelism.map_collect(npair_kl, |rs| {
+            let packed = &half[npair_ao * rs..npair_ao * (rs + 1)];
+            let mut full = thread_full.get();
+            let mut tmp = thread_tmp.get();
+            unpack_tril(packed, nao, &mut full);
+            transform_pair(&full, nao, nao, coeff_i, nmo_i, coeff_j, nmo_j, packed_ij, &mut tmp)
+        }).into_iter().flatten().collect();
+        return (out, vec![npair_ij, npair_kl]);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::cint_wrapper::*;
+use crate::cint_context::CINTEvalContext;
+use crate::cint_parallel::ThreadBuffers;
+use crate::{CintType, CINTR2CDATA};
+
+/// Iterator of batches of one large integral (s1 symmetry), split along one axis of shells.
+///
+/// Created by [`CINTR2CDATA::integral_s1_batches`]. Each item is `(shl_slices, (out, out_shape))` of one batch,
+/// where `shl_slices` is the full shell slices with the batched axis replaced by shells of this batch.
+/// Optimizer and thread-local caches are created once and reused by all batches.
+pub struct IntegralBatches<'a, T>
+where
+    T: Integrator
+{
+    ctx: CINTEvalContext<'a, T>,
+    shl_slices: Vec<[i32; 2]>,
+    axis: usize,
+    batches: Vec<[i32; 2]>,
+    thread_cache: ThreadBuffers<f64>,
+    idx_batch: usize,
+}
+
+impl<T> IntegralBatches<'_, T>
+where
+    T: Integrator
+{
+    /// Shell slices along batched axis of all batches.
+    pub fn batches(&self) -> &[[i32; 2]] {
+        &self.batches
+    }
+}
+
+impl<T> Iterator for IntegralBatches<'_, T>
+where
+    T: Integrator
+{
+    type Item = (Vec<[i32; 2]>, (Vec<f64>, Vec<usize>));
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = *self.batches.get(self.idx_batch)?;
+        self.idx_batch += 1;
+
+        let mut shl_slices = self.shl_slices.clone();
+        shl_slices[self.axis] = batch;
+        let mut out_shape = self.ctx.cgto_shape::<T>(&shl_slices);
+        if T::n_comp() > 1 { out_shape.push(T::n_comp()); }
+        let mut out = vec![0.; out_shape.iter().product::<usize>()];
+        self.ctx.integral_s1_inplace_with_cache::<f64>(&mut out, &shl_slices, &self.thread_cache);
+        return Some((shl_slices, (out, out_shape)));
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.batches.len() - self.idx_batch;
+        (n, Some(n))
+    }
+}
+
+impl<T> ExactSizeIterator for IntegralBatches<'_, T> where T: Integrator {}
+
+impl CINTR2CDATA {
+    /// Split shells of `shl_slices` along `axis` into batches, so that output of each batch (s1 symmetry)
+    /// does not exceed `max_memory` bytes.
+    ///
+    /// A single shell whose output already exceeds `max_memory` is still given as one batch.
+    pub fn batch_shl_slices<T> (&self, shl_slices: Option<&[[i32; 2]]>, axis: usize, max_memory: usize) -> Vec<[i32; 2]>
+    where
+        T: Integrator
+    {
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices.to_vec(),
+            None => vec![[0, self.c_nbas]; T::n_center()],
+        };
+        self.check_shl_slices::<T>(&shl_slices).unwrap();
+        if axis >= T::n_center() {
+            panic!("batch axis {axis} exceeds number of centers {}", T::n_center());
+        }
+
+        // bytes of output per CGTO along batched axis
+        let cgto_shape = self.cgto_shape::<T>(&shl_slices);
+        let size_per_cgto = std::mem::size_of::<f64>() * T::n_comp()
+            * cgto_shape.iter().enumerate().filter(|&(n, _)| n != axis).map(|(_, &s)| s).product::<usize>();
+
+        let [shl_start, shl_stop] = shl_slices[axis];
+        let mut batches = vec![];
+        let mut batch_start = shl_start;
+        let mut batch_memory = 0;
+        for shl in shl_start..shl_stop {
+            let shl_memory = size_per_cgto * self.cgto_size(shl);
+            if shl > batch_start && batch_memory + shl_memory > max_memory {
+                batches.push([batch_start, shl]);
+                batch_start = shl;
+                batch_memory = 0;
+            }
+            batch_memory += shl_memory;
+        }
+        if shl_stop > batch_start {
+            batches.push([batch_start, shl_stop]);
+        }
+        return batches;
+    }
+
+    /// Evaluate a large integral (s1 symmetry) batch by batch along `axis`, with output of each batch not
+    /// exceeding `max_memory` bytes (unless a single shell is larger).
+    ///
+    /// For example, `int3c2e` batched by auxiliary shells (`axis = 2`):
+    /// ```no_run
+    /// for (shl_slices, (out, out_shape)) in cint_data.integral_s1_batches::<int3c2e>(None, 2, 1 << 30) {
+    ///     /* use f-contiguous `out` of auxiliary shells shl_slices[2] */
+    /// }
+    /// ```
+    pub fn integral_s1_batches<T> (&self, shl_slices: Option<&[[i32; 2]]>, axis: usize, max_memory: usize) -> IntegralBatches<'_, T>
+    where
+        T: Integrator
+    {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by batched integral evaluation");
+        }
+        let batches = self.batch_shl_slices::<T>(shl_slices, axis, max_memory);
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices.to_vec(),
+            None => vec![[0, self.c_nbas]; T::n_center()],
+        };
+        let ctx = self.eval_context::<T, f64>();
+        // cache of full shell slices is large enough for any batch
+        let cache_size = ctx.size_of_cache::<T>(&shl_slices);
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
+        IntegralBatches { ctx, shl_slices, axis, batches, thread_cache, idx_batch: 0 }
+    }
+}
@@ -0,0 +1,151 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::Arc;
+use crate::cint_wrapper::*;
+use crate::cint_optimizer::{CINTOptimizer, MoleculeDigest};
+use crate::{CintType, CINTR2CDATA};
+use crate::utilities::*;
+
+/// Evaluation context of one integral call.
+///
+/// Optimizer of integrator `T` and GTO type (spheric, cartesian or spinor) are owned by this context,
+/// instead of `CINTR2CDATA` itself. Integral engines only read `CINTR2CDATA` through this context,
+/// so integrals of different integrators or GTO types can be evaluated concurrently on one shared
+/// `CINTR2CDATA` (such as `Arc<CINTR2CDATA>`).
+///
+/// Optimizer is taken from optimizer cache of `CINTR2CDATA` (see [`CINTR2CDATA::cached_optimizer`]),
+/// so repeated calls of the same integrator (such as many small slices of `int2e`) reuse one optimizer.
+///
+/// ```no_run
+/// let ctx = CINTEvalContext::<int2e>::new(&cint_data, CintType::Spheric);
+/// let mut buf = vec![0.0; ctx.size_of_buffer::<int2e>(&[[0, 1]; 4])];
+/// unsafe { ctx.integral_block::<f64>(&mut buf, &[0, 0, 0, 0], &[], &mut []); }
+/// ```
+pub struct CINTEvalContext<'a, T>
+where
+    T: Integrator
+{
+    cint_data: Cow<'a, CINTR2CDATA>,
+    optimizer: Arc<CINTOptimizer>,
+    _integrator: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> CINTEvalContext<'a, T>
+where
+    T: Integrator
+{
+    /// Create evaluation context (with optimizer of integrator `T`) for GTO type `cint_type`.
+    ///
+    /// `cint_data` is borrowed if its GTO type is the same to `cint_type`; otherwise a copy with
+    /// the requested GTO type is made, and `cint_data` itself is not modified.
+    pub fn new(cint_data: &'a CINTR2CDATA, cint_type: CintType) -> Self {
+        // optimizer does not depend on GTO type
+        let optimizer = cint_data.cached_optimizer::<T>();
+        let cint_data = match cint_data.cint_type == cint_type {
+            true => Cow::Borrowed(cint_data),
+            false => {
+                let mut cint_data = cint_data.clone_without_optimizer();
+                cint_data.cint_type = cint_type;
+                Cow::Owned(cint_data)
+            },
+        };
+        CINTEvalContext { cint_data, optimizer, _integrator: PhantomData }
+    }
+
+    /// GTO type of this evaluation context.
+    pub fn cint_type(&self) -> CintType {
+        self.cint_data.cint_type
+    }
+
+    /// Smallest unit of electron-integral function from libcint, with optimizer of this context.
+    ///
+    /// See also [`CINTR2CDATA::integral_block`]; all notes there also apply here.
+    ///
+    /// # Safety
+    ///
+    /// `out` should hold the block of `shls` in buffer of `shape` (including all components), and `cache`
+    /// should not be smaller than `size_of_cache`; libcint does not check either.
+    pub unsafe fn integral_block<F> (&self, out: &mut [F], shls: &[i32], shape: &[i32], cache: &mut [f64])
+    where
+        F: FF64
+    {
+        self.cint_data.integral_block_with_optimizer::<T, F>(out.as_mut_ptr(), shls, shape, cache, self.optimizer.as_ptr());
+    }
+
+    /// Same to [`Self::integral_block`], but output is given by raw pointer.
+    ///
+    /// Used by parallel engines writing disjoint blocks of one output buffer (see `DisjointMut`).
+    ///
+    /// # Safety
+    ///
+    /// Same to [`Self::integral_block`]; in addition, `out` should be valid for writes of the whole block
+    /// (`DisjointMut::ptr_at` with extent of the block), and no other thread may access the block meanwhile.
+    pub unsafe fn integral_block_ptr<F> (&self, out: *mut F, shls: &[i32], shape: &[i32], cache: &mut [f64])
+    where
+        F: FF64
+    {
+        self.cint_data.integral_block_with_optimizer::<T, F>(out, shls, shape, cache, self.optimizer.as_ptr());
+    }
+}
+
+impl<T> Deref for CINTEvalContext<'_, T>
+where
+    T: Integrator
+{
+    type Target = CINTR2CDATA;
+    fn deref(&self) -> &Self::Target {
+        &self.cint_data
+    }
+}
+
+impl CINTR2CDATA {
+
+    /// GTO type for evaluating integrals with float type `F`.
+    ///
+    /// Complex float type always refers to spinor; otherwise GTO type of `self` is used
+    /// (and float type check will fail afterwards if `self` is spinor).
+    pub fn cint_type_of<F> (&self) -> CintType
+    where
+        F: FF64
+    {
+        match std::mem::size_of::<F>() {
+            16 => CintType::Spinor,
+            _ => self.cint_type,
+        }
+    }
+
+    /// Create evaluation context of integrator `T` for float type `F`.
+    ///
+    /// See also [`CINTEvalContext`].
+    pub fn eval_context<T, F> (&self) -> CINTEvalContext<'_, T>
+    where
+        T: Integrator, F: FF64
+    {
+        CINTEvalContext::new(self, self.cint_type_of::<F>())
+    }
+
+    /// Optimizer of integrator `T` from optimizer cache.
+    ///
+    /// Optimizer cache is keyed by integrator, and shared by copies (`Clone`) of `CINTR2CDATA`.
+    /// Cached optimizer is reused only if `c_atm`, `c_bas` and `c_env` are the same to those it was
+    /// generated for; otherwise (such as geometry or basis changed) a new optimizer is generated.
+    pub fn cached_optimizer<T> (&self) -> Arc<CINTOptimizer>
+    where
+        T: Integrator
+    {
+        let digest = MoleculeDigest::new(&self.c_atm, &self.c_bas, &self.c_env);
+        let mut cache = self.opt_cache.lock().unwrap();
+        cache.get_cint_with_digest::<T>(&digest, &self.c_atm, self.c_natm, &self.c_bas, self.c_nbas, &self.c_env)
+    }
+
+    /// Number of optimizers in optimizer cache.
+    pub fn optimizer_cache_len(&self) -> usize {
+        self.opt_cache.lock().unwrap().len()
+    }
+
+    /// Remove all optimizers in optimizer cache.
+    pub fn clear_optimizer_cache(&self) {
+        self.opt_cache.lock().unwrap().clear();
+    }
+}
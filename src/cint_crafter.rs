@@ -4,23 +4,25 @@ use std::ptr::{null_mut, null};
 use std::sync::Mutex;
 use itertools::{Format, Itertools};
 use rayon::prelude::*;
-use rayon::{max_num_threads, current_thread_index};
+use rayon::max_num_threads;
 use crate::cint_wrapper::*;
 use crate::cint;
+use crate::cint::CINTOpt;
+use crate::cint_optimizer::CINTOptimizer;
+use crate::cint_context::CINTEvalContext;
+use crate::cint_parallel::ThreadBuffers;
+use crate::cint_schedule::schedule_by_cost;
 use crate::{CintType, CINTR2CDATA};
 use crate::utilities::*;
 use num_complex::*;
 
-unsafe impl Send for CINTR2CDATA {}
-unsafe impl Sync for CINTR2CDATA {}
-
 impl CINTR2CDATA {
 
     /* #region optimizer */
 
     /// Remove optimizer
     pub fn optimizer_destruct(&mut self) {
-        unsafe { cint::CINTdel_optimizer(&mut self.c_opt); }
+        self.c_opt = CINTOptimizer::null();
     }
 
     /// Optimizer of libcint intors.
@@ -37,14 +39,7 @@ impl CINTR2CDATA {
     where
         T: Integrator
     {
-        self.optimizer_destruct();
-        unsafe {
-            T::optimizer(
-                &mut self.c_opt,
-                self.c_atm.as_ptr(), self.c_natm,
-                self.c_bas.as_ptr(), self.c_nbas,
-                self.c_env.as_ptr());
-        }
+        self.c_opt = CINTOptimizer::new::<T>(&self.c_atm, self.c_natm, &self.c_bas, self.c_nbas, &self.c_env);
     }
 
     /* #endregion */
@@ -247,7 +242,7 @@ impl CINTR2CDATA {
     /// // maximum cache for the whole molecule
     /// println!("{:?}", cint_data.max_cache_size::<int2e>(&vec![]));
     /// ```
-    pub fn size_of_cache<T> (&mut self, shls_slice: &[[i32; 2]]) -> usize
+    pub fn size_of_cache<T> (&self, shls_slice: &[[i32; 2]]) -> usize
     where
         T: Integrator
     {
@@ -313,6 +308,19 @@ impl CINTR2CDATA {
     /// 
     /// This function does not check type (float or complex) of `out`. Type check will be checked in caller.
     pub unsafe fn integral_block<T, F> (&self, out: &mut [F], shls: &[i32], shape: &[i32], cache: &mut [f64])
+    where
+        T: Integrator, F: FF64
+    {
+        self.integral_block_with_optimizer::<T, F>(out.as_mut_ptr(), shls, shape, cache, self.c_opt.as_ptr());
+    }
+
+    /// Smallest unit of electron-integral function from libcint, with optimizer given by caller.
+    ///
+    /// Optimizer should be generated for the same integrator `T` (or be null).
+    /// Output is given by raw pointer, so that parallel engines could write disjoint blocks of one output
+    /// buffer without creating overlapping mutable slices.
+    /// See also [`Self::integral_block`] and [`crate::cint_context::CINTEvalContext`].
+    pub unsafe fn integral_block_with_optimizer<T, F> (&self, out: *mut F, shls: &[i32], shape: &[i32], cache: &mut [f64], c_opt: *const CINTOpt)
     where
         T: Integrator, F: FF64
     {
@@ -327,27 +335,27 @@ impl CINTR2CDATA {
         match self.cint_type {
             CintType::Spheric => unsafe {
                 T::integral_sph(
-                    out.as_mut_ptr() as *mut f64,
+                    out as *mut f64,
                     shape_ptr, shls.as_ptr(),
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
-                    self.c_env.as_ptr(), self.c_opt, cache_ptr)
+                    self.c_env.as_ptr(), c_opt, cache_ptr)
                 },
             CintType::Cartesian => unsafe {
                 T::integral_cart(
-                    out.as_mut_ptr() as *mut f64,
+                    out as *mut f64,
                     shape_ptr, shls.as_ptr(),
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
-                    self.c_env.as_ptr(), self.c_opt, cache_ptr)
+                    self.c_env.as_ptr(), c_opt, cache_ptr)
                 },
             CintType::Spinor => unsafe {
                 T::integral_spinor(
-                    out.as_mut_ptr() as *mut cint::__BindgenComplex<f64>,
+                    out as *mut cint::__BindgenComplex<f64>,
                     shape_ptr, shls.as_ptr(),
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
-                    self.c_env.as_ptr(), self.c_opt, cache_ptr)
+                    self.c_env.as_ptr(), c_opt, cache_ptr)
                 },
         };
     }
@@ -356,9 +364,120 @@ impl CINTR2CDATA {
     /// 
     /// This function a low-level API, which is not intended to be called by user.
     /// This function only works for f-contiguous integral (PySCF convention).
-    pub fn integral_s1_inplace<T, F> (&mut self, out: &mut Vec<F>, shl_slices: &[[i32; 2]])
+    pub fn integral_s1_inplace<T, F> (&self, out: &mut [F], shl_slices: &[[i32; 2]])
+    where
+        T: Integrator, F: FF64
+    {
+        self.eval_context::<T, F>().integral_s1_inplace::<F>(out, shl_slices);
+    }
+
+    pub fn integral_s1_inner<T, F> (&self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<F>, Vec<usize>)
+    where
+        T: Integrator, F: FF64
+    {
+        // specify shl_slices
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices,
+            None => &vec![[0, self.c_nbas]; T::n_center()],
+        };
+        // evaluation context: optimizer and GTO type (by float type) of this call
+        let ctx = self.eval_context::<T, F>();
+        // specify and allocate output
+        let mut out_shape = ctx.cgto_shape::<T>(&shl_slices);
+        if T::n_comp() > 1 { out_shape.push(T::n_comp()); }
+        let out_size = out_shape.iter().product::<usize>();
+        let mut out = vec![F::zero(); out_size];
+        // main integral engine
+        ctx.integral_s1_inplace::<F>(&mut out, &shl_slices);
+        return (out, out_shape);
+    }
+
+    pub fn integral_s1<T> (&self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
+    where
+        T: Integrator
+    {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor should be called by `integral_s1_spinor<Integrator>` or `integral_s1_inner<Integrator, Complex<f64>>`");
+        }
+        return self.integral_s1_inner::<T, f64>(shl_slices);
+    }
+
+    pub fn integral_spinor_s1<T> (&self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<Complex<f64>>, Vec<usize>)
+    where
+        T: Integrator
+    {
+        // complex float type selects spinor GTO in evaluation context; `self` is not modified
+        return self.integral_s1_inner::<T, Complex<f64>>(shl_slices);
+    }
+    
+    pub fn integral_s2ij_inplace<T, F> (&self, out: &mut [F], shl_slices: &[[i32; 2]])
+    where
+        T: Integrator, F: FF64
+    {
+        self.eval_context::<T, F>().integral_s2ij_inplace::<F>(out, shl_slices);
+    }
+
+    pub fn integral_s2ij_inner<T, F> (&self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<F>, Vec<usize>)
     where
         T: Integrator, F: FF64
+    {
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices,
+            None => &vec![[0, self.c_nbas]; T::n_center()],
+        };
+        let ctx = self.eval_context::<T, F>();
+        let mut out_shape = ctx.cgto_shape_s2ij::<T>(&shl_slices).unwrap();
+        if T::n_comp() > 1 { out_shape.push(T::n_comp()); }
+        let out_size = out_shape.iter().product::<usize>();
+        let mut out = vec![F::zero(); out_size];
+        ctx.integral_s2ij_inplace::<F>(&mut out, &shl_slices);
+        return (out, out_shape);
+    }
+
+    pub fn integral_s2ij<T> (&self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
+    where
+        T: Integrator
+    {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor should be called by `integral_s1_spinor<Integrator>` or `integral_s1_inner<Integrator, Complex<f64>>`");
+        }
+        return self.integral_s2ij_inner::<T, f64>(shl_slices);
+    }
+
+    pub fn integral_spinor_s2ij<T> (&self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<Complex<f64>>, Vec<usize>)
+    where
+        T: Integrator
+    {
+        eprintln!("`integral_spinor_s2ij` should generally not be called, since spinor may not show s2ij symmetry.");
+        return self.integral_s2ij_inner::<T, Complex<f64>>(shl_slices);
+    }
+}
+
+impl<T> CINTEvalContext<'_, T>
+where
+    T: Integrator
+{
+    /// Main integral engine for s1 symmetry.
+    /// 
+    /// This function a low-level API, which is not intended to be called by user.
+    /// This function only works for f-contiguous integral (PySCF convention).
+    pub fn integral_s1_inplace<F> (&self, out: &mut [F], shl_slices: &[[i32; 2]])
+    where
+        F: FF64
+    {
+        // cache: thread-local
+        let cache_size = self.size_of_cache::<T>(shl_slices);
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
+        self.integral_s1_inplace_with_cache::<F>(out, shl_slices, &thread_cache);
+    }
+
+    /// Main integral engine for s1 symmetry, with thread-local cache given by caller.
+    ///
+    /// Cache can be reused by several calls (such as batches of one large integral), as long as it is not
+    /// smaller than `size_of_cache` of `shl_slices`.
+    pub(crate) fn integral_s1_inplace_with_cache<F> (&self, out: &mut [F], shl_slices: &[[i32; 2]], thread_cache: &ThreadBuffers<f64>)
+    where
+        F: FF64
     {
         /* #region 1. dimension definition and sanity check */
 
@@ -371,37 +490,48 @@ impl CINTR2CDATA {
         let cgto_shape_i32 = cgto_shape.iter().map(|&v| v as i32).collect::<Vec<i32>>();
         let index_shape = shl_slices.iter().map(|[shl_start, shl_stop]| (shl_stop - shl_start) as usize).collect_vec();
         let cgto_locs_rel = self.cgto_loc_slices_relative(shl_slices);
+        let out_size = cgto_shape.iter().product::<usize>() * n_comp;
+        if out.len() < out_size {
+            panic!("size of output {} is smaller than required {out_size}", out.len());
+        }
 
         /* #endregion */
 
         /* #region 2. preparation for integral engine */
 
-        // optimizer (make integral faster)
-        self.optimizer::<T>();
-
-        // cache: thread-local
+        // cache: thread-local, given by caller
         let cache_size = self.size_of_cache::<T>(shl_slices);
-        let thread_cache = (0..rayon::current_num_threads()).map(|n| {Mutex::new(vec![0.; cache_size])}).collect_vec();
+        if thread_cache.size() < cache_size {
+            panic!("size of thread cache {} is smaller than required {cache_size}", thread_cache.size());
+        }
 
-        // out: enable mut vector by passing immut slice
-        let out_const_slice = out.as_slice();
+        // out: shared by threads, each parallel task writes its own (disjoint) blocks
+        let out = DisjointMut::new(out);
 
         // reverse iteration for f-contiguous
         let index_shape_rev = index_shape.into_iter().rev().collect_vec();
         let shl_slices_rev = shl_slices.iter().rev().collect_vec();
         let cgto_locs_rel_rev = cgto_locs_rel.iter().rev().collect_vec();
         let cgto_shape_rev = cgto_shape.iter().rev().collect_vec();
+        let cgto_width_rev = |k: usize, idx: usize| cgto_locs_rel_rev[k][idx + 1] - cgto_locs_rel_rev[k][idx];
+        let out_shape = cgto_shape.iter().copied().chain([n_comp]).collect_vec();
 
         /* #endregion */
 
         /* #region 3. parallel integral generation */
 
-        // Following code of parallel is not fearless.
-        // Variable `out` will be written in parallel, which should be considered racing,
-        // when calling `self.integral_block::<T>`,
-        // and racing would not actually happen if I am careful.
+        // Each task (outer two shells) writes integral blocks of its own shells; blocks of different tasks
+        // never overlap, so writing through `DisjointMut` is free of data racing.
+
+        // expensive shell pairs are dispatched first
+        let task_costs = (0..(index_shape_rev[0] * index_shape_rev[1])).map(|idx_01| {
+            let shl_0 = (idx_01 / index_shape_rev[1]) as i32 + shl_slices_rev[0][0];
+            let shl_1 = (idx_01 % index_shape_rev[1]) as i32 + shl_slices_rev[1][0];
+            self.shell_tuple_cost(&[shl_1, shl_0])
+        }).collect_vec();
+        let task_order = schedule_by_cost(&task_costs);
 
-        (0..(index_shape_rev[0] * index_shape_rev[1])).into_par_iter().for_each(|idx_01| {
+        self.parallelism.for_each_ordered(&task_order, |idx_01| {
             let idx_0 = idx_01 / index_shape_rev[1];
             let idx_1 = idx_01 % index_shape_rev[1];
             let shl_0 = idx_0 as i32 + shl_slices_rev[0][0];
@@ -409,18 +539,18 @@ impl CINTR2CDATA {
             let cgto_0 = cgto_locs_rel_rev[0][idx_0];
             let cgto_1 = cgto_locs_rel_rev[1][idx_1];
             
-            let thread_index = current_thread_index().unwrap_or(0);
-            let mut cache = thread_cache[thread_index].lock().unwrap();
+            let mut cache = thread_cache.get();
             
             match n_center {
                 2 =>
                 {
                     let shls = [shl_1, shl_0];
                     let offset = cgto_1 + cgto_shape_rev[1] * cgto_0;
+                    let block_shape = [cgto_width_rev(1, idx_1), cgto_width_rev(0, idx_0), n_comp];
+                    let extent = block_extent(&block_shape, &out_shape);
     
                     unsafe {
-                        let out_with_offset = cast_mut_slice(&out_const_slice[offset..]);
-                        self.integral_block::<T, F>(out_with_offset, &shls, &cgto_shape_i32, &mut cache);
+                        self.integral_block_ptr::<F>(out.ptr_at(offset, extent), &shls, &cgto_shape_i32, &mut cache);
                     }
                 },
 
@@ -430,10 +560,11 @@ impl CINTR2CDATA {
                     let cgto_2 = cgto_locs_rel_rev[2][idx_2];
                     let shls = [shl_2, shl_1, shl_0];
                     let offset = cgto_2 + cgto_shape_rev[2] * (cgto_1 + cgto_shape_rev[1] * cgto_0);
+                    let block_shape = [cgto_width_rev(2, idx_2), cgto_width_rev(1, idx_1), cgto_width_rev(0, idx_0), n_comp];
+                    let extent = block_extent(&block_shape, &out_shape);
     
                     unsafe {
-                        let out_with_offset = cast_mut_slice(&out_const_slice[offset..]);
-                        self.integral_block::<T, F>(out_with_offset, &shls, &cgto_shape_i32, &mut cache);
+                        self.integral_block_ptr::<F>(out.ptr_at(offset, extent), &shls, &cgto_shape_i32, &mut cache);
                     }
                 },
 
@@ -446,10 +577,13 @@ impl CINTR2CDATA {
                         let cgto_3 = cgto_locs_rel_rev[3][idx_3];
                         let shls = [shl_3, shl_2, shl_1, shl_0];
                         let offset = cgto_3 + cgto_shape_rev[3] * (cgto_2 + cgto_shape_rev[2] * (cgto_1 + cgto_shape_rev[1] * cgto_0));
+                        let block_shape = [
+                            cgto_width_rev(3, idx_3), cgto_width_rev(2, idx_2),
+                            cgto_width_rev(1, idx_1), cgto_width_rev(0, idx_0), n_comp];
+                        let extent = block_extent(&block_shape, &out_shape);
         
                         unsafe {
-                            let out_with_offset = cast_mut_slice(&out_const_slice[offset..]);
-                            self.integral_block::<T, F>(out_with_offset, &shls, &cgto_shape_i32, &mut cache);
+                            self.integral_block_ptr::<F>(out.ptr_at(offset, extent), &shls, &cgto_shape_i32, &mut cache);
                         }
                     }
                 },
@@ -458,56 +592,11 @@ impl CINTR2CDATA {
             }
         });
         /* #endregion */
-
-        /* #region 4. cleanup */
-        self.optimizer_destruct();
-        /* #endregion */
     }
 
-    pub fn integral_s1_inner<T, F> (&mut self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<F>, Vec<usize>)
+    pub fn integral_s2ij_inplace<F> (&self, out: &mut [F], shl_slices: &[[i32; 2]])
     where
-        T: Integrator, F: FF64
-    {
-        // specify shl_slices
-        let shl_slices = match shl_slices {
-            Some(shl_slices) => shl_slices,
-            None => &vec![[0, self.c_nbas]; T::n_center()],
-        };
-        // specify and allocate output
-        let mut out_shape = self.cgto_shape::<T>(&shl_slices);
-        if T::n_comp() > 1 { out_shape.push(T::n_comp()); }
-        let out_size = out_shape.iter().product::<usize>();
-        let mut out = Vec::<F>::with_capacity(out_size);
-        unsafe { out.set_len(out_size) };
-        // main integral engine
-        self.integral_s1_inplace::<T, _>(&mut out, &shl_slices);
-        return (out, out_shape);
-    }
-
-    pub fn integral_s1<T> (&mut self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
-    where
-        T: Integrator
-    {
-        if self.cint_type == CintType::Spinor {
-            panic!("Spinor should be called by `integral_s1_spinor<Integrator>` or `integral_s1_inner<Integrator, Complex<f64>>`");
-        }
-        return self.integral_s1_inner::<T, f64>(shl_slices);
-    }
-
-    pub fn integral_spinor_s1<T> (&mut self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<Complex<f64>>, Vec<usize>)
-    where
-        T: Integrator
-    {
-        let cint_type = self.cint_type;
-        self.set_cint_type(&CintType::Spinor);
-        let result = self.integral_s1_inner::<T, _>(shl_slices);
-        self.set_cint_type(&cint_type);
-        return result;
-    }
-    
-    pub fn integral_s2ij_inplace<T, F> (&mut self, out: &mut Vec<F>, shl_slices: &[[i32; 2]])
-    where
-        T: Integrator, F: FF64
+        F: FF64
     {
         /* #region 1. dimension definition and sanity check */
 
@@ -519,42 +608,46 @@ impl CINTR2CDATA {
         let cgto_s2ij_shape = self.cgto_shape_s2ij::<T>(shl_slices).unwrap();
         let index_shape = shl_slices.iter().map(|[shl_start, shl_stop]| (shl_stop - shl_start) as usize).collect_vec();
         let cgto_locs_rel = self.cgto_loc_slices_relative(shl_slices);
+        let out_size = cgto_s2ij_shape.iter().product::<usize>() * n_comp;
+        if out.len() < out_size {
+            panic!("size of output {} is smaller than required {out_size}", out.len());
+        }
 
         /* #endregion */
 
         /* #region 2. preparation for integral engine */
 
-        // optimizer (make integral faster)
-        self.optimizer::<T>();
-
         // cache: thread-local
         let cache_size = self.size_of_cache::<T>(shl_slices);
         let buf_size = self.size_of_buffer::<T>(shl_slices);
-        let thread_cache = (0..rayon::current_num_threads()).map(|n| {Mutex::new(vec![0.; cache_size])}).collect_vec();
-        let thread_buf = (0..rayon::current_num_threads()).map(|n| {Mutex::new(vec![F::zero(); buf_size])}).collect_vec();
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
+        let thread_buf = ThreadBuffers::new(&self.parallelism, buf_size, F::zero());
 
-        // out: enable mut vector by passing immut slice
-        let out_const_slice = out.as_slice();
+        // out: shared by threads, each parallel task writes its own (disjoint) blocks
+        let out = DisjointMut::new(out);
 
         /* #endregion */
 
         /* #region 3. parallel integral generation */
 
-        // Following code of parallel is not fearless.
-        // Variable `out` will be written in parallel, which should be considered racing;
-        // and racing would not actually happen if I am careful.
+        // Each task (outermost shells) writes its own elements of triangular-packed output;
+        // elements of different tasks never overlap, so writing through `DisjointMut` is free of data racing.
         
         match n_center {
             2 => {
                 let out_s2ij_shape: [usize; 2] = [cgto_s2ij_shape, vec![n_comp]].concat().try_into().unwrap();
 
-                (0..index_shape[1]).into_par_iter().for_each(|idx_j| {
+                // task of shell j evaluates all shells i <= j
+                let task_costs = (0..index_shape[1]).map(|idx_j| {
+                    let shl_j = idx_j as i32 + shl_slices[1][0];
+                    (0..(idx_j + 1)).map(|idx_i| self.shell_tuple_cost(&[idx_i as i32 + shl_slices[0][0], shl_j])).sum::<f64>()
+                }).collect_vec();
+                let task_order = schedule_by_cost(&task_costs);
+
+                self.parallelism.for_each_ordered(&task_order, |idx_j| {
                     // thread-local variables
-                    let thread_index = current_thread_index().unwrap_or(0);
-                    let mut cache = thread_cache[thread_index].lock().unwrap();
-                    let mut buf = thread_buf[thread_index].lock().unwrap();
-                    // output
-                    let mut out = unsafe { cast_mut_slice(&out) };
+                    let mut cache = thread_cache.get();
+                    let mut buf = thread_buf.get();
                     // index computation and iteration
                     let shl_j = idx_j as i32 + shl_slices[1][0];
                     let cgto_j = cgto_locs_rel[1][idx_j];
@@ -563,14 +656,14 @@ impl CINTR2CDATA {
                         let cgto_i = cgto_locs_rel[0][idx_i];
                         // main integrator
                         let shls = [shl_i, shl_j];
-                        unsafe { self.integral_block::<T, _>(&mut buf, &shls, &vec![], &mut cache); }
+                        unsafe { self.integral_block::<F>(&mut buf, &shls, &[], &mut cache); }
                         // copy from buffer to output
                         let buf_shape = [self.cgto_size(shl_i), self.cgto_size(shl_j), n_comp];
                         let out_offsets = [cgto_i, cgto_j, 0];
                         if idx_i != idx_j {
-                            copy_3d_s2ij_offdiag(out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape);
+                            unsafe { copy_3d_s2ij_offdiag(&out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape); }
                         } else {
-                            copy_3d_s2ij_diag(out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape);
+                            unsafe { copy_3d_s2ij_diag(&out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape); }
                         }
                     }
                 })
@@ -579,13 +672,15 @@ impl CINTR2CDATA {
             3 => {
                 let out_s2ij_shape: [usize; 3] = [cgto_s2ij_shape, vec![n_comp]].concat().try_into().unwrap();
 
-                (0..index_shape[2]).into_par_iter().for_each(|idx_k| {
+                let task_costs = (0..index_shape[2]).map(|idx_k| {
+                    self.shell_tuple_cost(&[idx_k as i32 + shl_slices[2][0]])
+                }).collect_vec();
+                let task_order = schedule_by_cost(&task_costs);
+
+                self.parallelism.for_each_ordered(&task_order, |idx_k| {
                     // thread-local variables
-                    let thread_index = current_thread_index().unwrap_or(0);
-                    let mut cache = thread_cache[thread_index].lock().unwrap();
-                    let mut buf = thread_buf[thread_index].lock().unwrap();
-                    // output
-                    let mut out = unsafe { cast_mut_slice(&out) };
+                    let mut cache = thread_cache.get();
+                    let mut buf = thread_buf.get();
                     // index computation and iteration
                     let shl_k = idx_k as i32 + shl_slices[2][0];
                     let cgto_k = cgto_locs_rel[2][idx_k];
@@ -597,14 +692,14 @@ impl CINTR2CDATA {
                             let cgto_j = cgto_locs_rel[0][idx_j];
                             // main integrator
                             let shls = [shl_i, shl_j, shl_k];
-                            unsafe { self.integral_block::<T, _>(&mut buf, &shls, &vec![], &mut cache); }
+                            unsafe { self.integral_block::<F>(&mut buf, &shls, &[], &mut cache); }
                             // copy from buffer to output
                             let buf_shape = [self.cgto_size(shl_i), self.cgto_size(shl_j), self.cgto_size(shl_k), n_comp];
                             let out_offsets = [cgto_i, cgto_j, cgto_k, 0];
                             if idx_i != idx_j {
-                                copy_4d_s2ij_offdiag(out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape);
+                                unsafe { copy_4d_s2ij_offdiag(&out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape); }
                             } else {
-                                copy_4d_s2ij_diag(out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape);
+                                unsafe { copy_4d_s2ij_diag(&out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape); }
                             }
                         }
                     }
@@ -614,13 +709,17 @@ impl CINTR2CDATA {
             4 => {
                 let out_s2ij_shape: [usize; 4] = [cgto_s2ij_shape, vec![n_comp]].concat().try_into().unwrap();
 
-                (0..index_shape[2]*index_shape[3]).into_par_iter().for_each(|idx_kl| {
+                let task_costs = (0..index_shape[2]*index_shape[3]).map(|idx_kl| {
+                    let shl_k = (idx_kl % index_shape[2]) as i32 + shl_slices[2][0];
+                    let shl_l = (idx_kl / index_shape[2]) as i32 + shl_slices[3][0];
+                    self.shell_tuple_cost(&[shl_k, shl_l])
+                }).collect_vec();
+                let task_order = schedule_by_cost(&task_costs);
+
+                self.parallelism.for_each_ordered(&task_order, |idx_kl| {
                     // thread-local variables
-                    let thread_index = current_thread_index().unwrap_or(0);
-                    let mut cache = thread_cache[thread_index].lock().unwrap();
-                    let mut buf = thread_buf[thread_index].lock().unwrap();
-                    // output
-                    let mut out = unsafe { cast_mut_slice(&out) };
+                    let mut cache = thread_cache.get();
+                    let mut buf = thread_buf.get();
                     // index computation and iteration
                     let idx_l = idx_kl / index_shape[2];
                     let idx_k = idx_kl % index_shape[2];
@@ -636,14 +735,14 @@ impl CINTR2CDATA {
                             let cgto_j = cgto_locs_rel[0][idx_j];
                             // main integrator
                             let shls = [shl_i, shl_j, shl_k, shl_l];
-                            unsafe { self.integral_block::<T, _>(&mut buf, &shls, &vec![], &mut cache); }
+                            unsafe { self.integral_block::<F>(&mut buf, &shls, &[], &mut cache); }
                             // copy from buffer to output
                             let buf_shape = [self.cgto_size(shl_i), self.cgto_size(shl_j), self.cgto_size(shl_k), self.cgto_size(shl_l), n_comp];
                             let out_offsets = [cgto_i, cgto_j, cgto_k, cgto_l, 0];
                             if idx_i != idx_j {
-                                copy_5d_s2ij_offdiag(out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape);
+                                unsafe { copy_5d_s2ij_offdiag(&out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape); }
                             } else {
-                                copy_5d_s2ij_diag(out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape);
+                                unsafe { copy_5d_s2ij_diag(&out, &out_offsets, &out_s2ij_shape, &buf, &buf_shape); }
                             }
                         }
                     }
@@ -653,48 +752,5 @@ impl CINTR2CDATA {
         }
         
         /* #endregion */
-
-        /* #region 4. cleanup */
-        self.optimizer_destruct();
-        /* #endregion */
-    }
-
-    pub fn integral_s2ij_inner<T, F> (&mut self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<F>, Vec<usize>)
-    where
-        T: Integrator, F: FF64
-    {
-        let shl_slices = match shl_slices {
-            Some(shl_slices) => shl_slices,
-            None => &vec![[0, self.c_nbas]; T::n_center()],
-        };
-        let mut out_shape = self.cgto_shape_s2ij::<T>(&shl_slices).unwrap();
-        if T::n_comp() > 1 { out_shape.push(T::n_comp()); }
-        let out_size = out_shape.iter().product::<usize>();
-        let mut out = Vec::<F>::with_capacity(out_size);
-        unsafe { out.set_len(out_size) };
-        self.integral_s2ij_inplace::<T, _>(&mut out, &shl_slices);
-        return (out, out_shape);
-    }
-
-    pub fn integral_s2ij<T> (&mut self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
-    where
-        T: Integrator
-    {
-        if self.cint_type == CintType::Spinor {
-            panic!("Spinor should be called by `integral_s1_spinor<Integrator>` or `integral_s1_inner<Integrator, Complex<f64>>`");
-        }
-        return self.integral_s2ij_inner::<T, f64>(shl_slices);
-    }
-
-    pub fn integral_spinor_s2ij<T> (&mut self, shl_slices: Option<&[[i32; 2]]>) -> (Vec<Complex<f64>>, Vec<usize>)
-    where
-        T: Integrator
-    {
-        eprintln!("`integral_spinor_s2ij` should generally not be called, since spinor may not show s2ij symmetry.");
-        let cint_type = self.cint_type;
-        self.set_cint_type(&CintType::Spinor);
-        let result = self.integral_s2ij_inner::<T, _>(shl_slices);
-        self.set_cint_type(&cint_type);
-        return result;
     }
 }
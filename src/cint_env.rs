@@ -0,0 +1,124 @@
+use std::ops::{Deref, DerefMut};
+use crate::cint::{PTR_RANGE_OMEGA, PTR_RINV_ZETA};
+use crate::{CINTR2CDATA, ExpCutoff};
+use crate::cint_optimizer::SharedOptimizerCache;
+
+/// Guard of scoped modifications to `CINTR2CDATA`.
+///
+/// The whole `CINTR2CDATA` is snapshotted when the guard is created, and restored when the guard is dropped.
+/// This covers `c_env` (global parameters before `PTR_ENV_START`, such as common origin, rinv origin, omega,
+/// as well as data appended after it, such as grid coordinates), and also `c_atm`, `c_bas` and GTO type, so that
+/// molecule data is always consistent after drop. Modifications through this guard will not leak into later
+/// integrals. Optimizer cache is shared with the snapshot; legacy optimizer `c_opt` is reset on drop.
+///
+/// ```no_run
+/// {
+///     let mut guard = cint_data.env_guard();
+///     guard.set_common_origin(&[0.0, 0.0, 1.0]);
+///     let (out, out_shape) = guard.integral_s1::<int1e_r>(None);
+/// }
+/// // common origin of `cint_data` is restored here
+/// ```
+pub struct EnvGuard<'a> {
+    cint_data: &'a mut CINTR2CDATA,
+    saved: CINTR2CDATA,
+}
+
+impl Deref for EnvGuard<'_> {
+    type Target = CINTR2CDATA;
+    fn deref(&self) -> &Self::Target {
+        self.cint_data
+    }
+}
+
+impl DerefMut for EnvGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.cint_data
+    }
+}
+
+impl Drop for EnvGuard<'_> {
+    fn drop(&mut self) {
+        // setters such as `set_grids` may truncate and overwrite data at the end of `c_env`, and `c_bas` may point
+        // into that data, so the whole struct is restored instead of global parameters only
+        std::mem::swap(self.cint_data, &mut self.saved);
+    }
+}
+
+impl CINTR2CDATA {
+
+    /// Create a guard that restores `CINTR2CDATA` on drop.
+    ///
+    /// See also [`EnvGuard`].
+    pub fn env_guard(&mut self) -> EnvGuard<'_> {
+        EnvGuard {
+            saved: self.clone(),
+            cint_data: self,
+        }
+    }
+
+    /// Clone of `CINTR2CDATA` without optimizer.
+    ///
+    /// Integrals evaluated on this clone never touch optimizer or `c_env` of `self`,
+    /// so this is safe to be used in parallel code.
+    /// Optimizer cache is not shared with `self`, since `c_env` of the clone is usually to be modified.
+    pub fn clone_without_optimizer(&self) -> CINTR2CDATA {
+        let mut cint_data = self.clone();
+        cint_data.opt_cache = SharedOptimizerCache::default();
+        return cint_data;
+    }
+
+    /// Evaluate `f` on a copy of `CINTR2CDATA` with common origin set to `orig`.
+    ///
+    /// ```no_run
+    /// let (out, out_shape) = cint_data.with_common_origin(&[0.0, 0.0, 1.0], |data| data.integral_s1::<int1e_r>(None));
+    /// ```
+    pub fn with_common_origin<R> (&self, orig: &[f64], f: impl FnOnce(&mut CINTR2CDATA) -> R) -> R {
+        let mut cint_data = self.clone_without_optimizer();
+        cint_data.set_common_origin(orig);
+        f(&mut cint_data)
+    }
+
+    /// Evaluate `f` on a copy of `CINTR2CDATA` with rinv origin set to `orig`.
+    pub fn with_rinv_origin<R> (&self, orig: &[f64], f: impl FnOnce(&mut CINTR2CDATA) -> R) -> R {
+        let mut cint_data = self.clone_without_optimizer();
+        cint_data.set_rinv_origin(orig);
+        f(&mut cint_data)
+    }
+
+    /// Evaluate `f` on a copy of `CINTR2CDATA` with rinv zeta (gaussian charge distribution of rinv operator) set to `zeta`.
+    pub fn with_rinv_zeta<R> (&self, zeta: f64, f: impl FnOnce(&mut CINTR2CDATA) -> R) -> R {
+        let mut cint_data = self.clone_without_optimizer();
+        cint_data.c_env[PTR_RINV_ZETA as usize] = zeta;
+        f(&mut cint_data)
+    }
+
+    /// Evaluate `f` on a copy of `CINTR2CDATA` with range-separation parameter set to `omega`.
+    ///
+    /// Positive `omega` gives long-range (erf) coulomb, negative `omega` gives short-range (erfc) coulomb,
+    /// and zero refers to full-range coulomb.
+    pub fn with_omega<R> (&self, omega: f64, f: impl FnOnce(&mut CINTR2CDATA) -> R) -> R {
+        let mut cint_data = self.clone_without_optimizer();
+        cint_data.c_env[PTR_RANGE_OMEGA as usize] = omega;
+        f(&mut cint_data)
+    }
+
+    /// Evaluate `f` on a copy of `CINTR2CDATA` with exponent cutoff set to `cutoff`.
+    pub fn with_exp_cutoff<R> (&self, cutoff: ExpCutoff, f: impl FnOnce(&mut CINTR2CDATA) -> R) -> R {
+        let mut cint_data = self.clone_without_optimizer();
+        cint_data.set_exp_cutoff(cutoff);
+        f(&mut cint_data)
+    }
+
+    /// Range-separation parameter `omega` in `c_env`.
+    pub fn get_omega(&self) -> f64 {
+        self.c_env[PTR_RANGE_OMEGA as usize]
+    }
+
+    /// Set range-separation parameter `omega` in `c_env`.
+    ///
+    /// This permanently changes `c_env`; consider [`Self::with_omega`] or [`Self::env_guard`] for scoped usage.
+    pub fn set_omega(&mut self, omega: f64) {
+        self.c_env[PTR_RANGE_OMEGA as usize] = omega;
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use crate::cint_wrapper::*;
+use crate::cint_schedule::{shell_weight, rys_roots_factor};
+use crate::{AoSym, CintType, CINTR2CDATA};
+
+/// Estimated memory and cost of one integral call, see [`CINTR2CDATA::estimate`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntegralEstimate {
+    /// bytes of output tensor
+    pub out_bytes: usize,
+    /// bytes of thread-local caches of all threads (`size_of_cache` for each thread)
+    pub cache_bytes: usize,
+    /// bytes of thread-local buffers of all threads (`size_of_buffer` for each thread; only `s2ij` uses buffer)
+    pub buffer_bytes: usize,
+    /// number of shell tuples evaluated, after symmetry
+    pub n_shell_tuples: usize,
+    /// relative FLOP estimate, by the same cost model of task scheduling (see [`CINTR2CDATA::shell_tuple_cost`])
+    pub flops: f64,
+}
+
+impl IntegralEstimate {
+    /// Peak bytes allocated by the integral call (output, caches and buffers).
+    pub fn total_bytes(&self) -> usize {
+        self.out_bytes + self.cache_bytes + self.buffer_bytes
+    }
+}
+
+impl CINTR2CDATA {
+    /// Estimate memory and cost of integral `T` before computing it.
+    ///
+    /// Memory of caches and buffers are counted for number of threads of current [`Parallelism`](crate::cint_parallel::Parallelism).
+    /// FLOP estimate is relative; it is only meaningful when compared to estimates of other integrals or shell slices.
+    ///
+    /// ```no_run
+    /// let est = cint_data.estimate::<int2e>(AoSym::S2ij, None);
+    /// println!("output {} MB, {} shell quartets", est.out_bytes >> 20, est.n_shell_tuples);
+    /// ```
+    pub fn estimate<T> (&self, aosym: AoSym, shl_slices: Option<&[[i32; 2]]>) -> IntegralEstimate
+    where
+        T: Integrator
+    {
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices.to_vec(),
+            None => vec![[0, self.c_nbas]; T::n_center()],
+        };
+        self.check_shl_slices::<T>(&shl_slices).unwrap();
+
+        let float_size = match self.cint_type {
+            CintType::Spinor => 16,
+            _ => 8,
+        };
+        let n_threads = self.parallelism.num_threads();
+        let index_shape = shl_slices.iter().map(|[shl_start, shl_stop]| (shl_stop - shl_start) as usize).collect::<Vec<usize>>();
+
+        let out_size = match aosym {
+            AoSym::S1 => self.cgto_shape::<T>(&shl_slices).iter().product::<usize>(),
+            AoSym::S2ij => self.cgto_shape_s2ij::<T>(&shl_slices).unwrap().iter().product::<usize>(),
+        } * T::n_comp();
+        let cache_bytes = self.size_of_cache::<T>(&shl_slices) * std::mem::size_of::<f64>() * n_threads;
+        let buffer_bytes = match aosym {
+            AoSym::S1 => 0,
+            AoSym::S2ij => self.size_of_buffer::<T>(&shl_slices) * float_size * n_threads,
+        };
+        let n_shell_tuples = match aosym {
+            AoSym::S1 => index_shape.iter().product::<usize>(),
+            AoSym::S2ij => index_shape[0] * (index_shape[0] + 1) / 2 * index_shape[2..].iter().product::<usize>(),
+        };
+
+        // Cost of shell tuple is product of shell weights times factor of total angular momentum; so shells are
+        // aggregated by angular momentum (weights summed), and summation over tuples is a convolution over axes.
+        let aggregate_axis = |shl_slice: &[i32; 2]| {
+            let mut weights = BTreeMap::<i32, f64>::new();
+            for shl in shl_slice[0]..shl_slice[1] {
+                let (l, w) = shell_weight(&self.c_bas, shl);
+                *weights.entry(l).or_default() += w;
+            }
+            weights
+        };
+        let convolve = |acc: BTreeMap<i32, f64>, weights: BTreeMap<i32, f64>| {
+            let mut result = BTreeMap::<i32, f64>::new();
+            for (&l_acc, &w_acc) in &acc {
+                for (&l, &w) in &weights {
+                    *result.entry(l_acc + l).or_default() += w_acc * w;
+                }
+            }
+            result
+        };
+        let (mut acc, n_skip) = match aosym {
+            AoSym::S1 => (BTreeMap::from([(0, 1.)]), 0),
+            AoSym::S2ij => {
+                // shell pairs i <= j
+                let mut acc = BTreeMap::<i32, f64>::new();
+                let [shl_start, shl_stop] = shl_slices[0];
+                for shl_j in shl_start..shl_stop {
+                    let (l_j, w_j) = shell_weight(&self.c_bas, shl_j);
+                    for shl_i in shl_start..(shl_j + 1) {
+                        let (l_i, w_i) = shell_weight(&self.c_bas, shl_i);
+                        *acc.entry(l_i + l_j).or_default() += w_i * w_j;
+                    }
+                }
+                (acc, 2)
+            },
+        };
+        for shl_slice in &shl_slices[n_skip..] {
+            acc = convolve(acc, aggregate_axis(shl_slice));
+        }
+        let flops = acc.iter().map(|(&l_sum, &w)| w * rys_roots_factor(l_sum)).sum::<f64>() * T::n_comp() as f64;
+
+        IntegralEstimate { out_bytes: out_size * float_size, cache_bytes, buffer_bytes, n_shell_tuples, flops }
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::cint_wrapper::*;
+use crate::cint_ao2mo::transform_pair;
+use crate::{CintType, CINTR2CDATA};
+
+/// Integrals with absolute value below this threshold are not written to FCIDUMP.
+pub const FCIDUMP_TOL: f64 = 1e-15;
+
+/// Active-space Hamiltonian in MO basis, to be written in FCIDUMP format.
+///
+/// Created by [`CINTR2CDATA::fcidump`]; fields could be adjusted (for example `ms2` or `orbsym`) before writing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FCIDump {
+    /// number of active orbitals
+    pub norb: usize,
+    /// number of active electrons
+    pub nelec: usize,
+    /// twice of spin projection (number of alpha minus beta electrons)
+    pub ms2: usize,
+    /// symmetry labels of active orbitals (1-based irreps); `ORBSYM` is all 1 if not given
+    pub orbsym: Option<Vec<usize>>,
+    /// symmetry label of target state
+    pub isym: usize,
+    /// core energy: nuclear repulsion and frozen-core energy
+    pub ecore: f64,
+    /// one-electron integrals (frozen core folded), packed `p <= q` (index `p + q * (q + 1) / 2`)
+    pub h1e: Vec<f64>,
+    /// two-electron integrals `(pq|rs)` with both pairs packed, f-contiguous `(npair, npair)`
+    pub h2e: Vec<f64>,
+}
+
+impl FCIDump {
+    /// Write FCIDUMP to file `path`.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Write FCIDUMP to `writer`. Orbital indices are 1-based; only s8-unique two-electron integrals are written.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let norb = self.norb;
+        let npair = norb * (norb + 1) / 2;
+        if self.h1e.len() != npair || self.h2e.len() != npair * npair {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("size of integrals does not match norb {norb}")));
+        }
+        let orbsym = match &self.orbsym {
+            Some(orbsym) if orbsym.len() != norb => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("length of orbsym {} is not norb {norb}", orbsym.len())));
+            },
+            Some(orbsym) => orbsym.clone(),
+            None => vec![1; norb],
+        };
+
+        writeln!(writer, " &FCI NORB={norb},NELEC={},MS2={},", self.nelec, self.ms2)?;
+        writeln!(writer, "  ORBSYM={}", orbsym.iter().map(|s| format!("{s},")).collect::<String>())?;
+        writeln!(writer, "  ISYM={},", self.isym)?;
+        writeln!(writer, " &END")?;
+
+        // pairs (i >= j) in order of packed index
+        let pairs = (0..norb).flat_map(|i| (0..(i + 1)).map(move |j| (i, j))).collect::<Vec<_>>();
+        for (ij, &(i, j)) in pairs.iter().enumerate() {
+            for (kl, &(k, l)) in pairs[..(ij + 1)].iter().enumerate() {
+                let v = self.h2e[ij + npair * kl];
+                if v.abs() >= FCIDUMP_TOL {
+                    writeln!(writer, "{v:24.16e} {:4} {:4} {:4} {:4}", i + 1, j + 1, k + 1, l + 1)?;
+                }
+            }
+        }
+        for (ij, &(i, j)) in pairs.iter().enumerate() {
+            let v = self.h1e[ij];
+            if v.abs() >= FCIDUMP_TOL {
+                writeln!(writer, "{v:24.16e} {:4} {:4} {:4} {:4}", i + 1, j + 1, 0, 0)?;
+            }
+        }
+        writeln!(writer, "{:24.16e} {:4} {:4} {:4} {:4}", self.ecore, 0, 0, 0, 0)?;
+        Ok(())
+    }
+}
+
+impl CINTR2CDATA {
+    /// Active-space Hamiltonian for FCIDUMP output.
+    ///
+    /// * `coeff` -
+    ///     f-contiguous MO coefficients of shape `(nao, nmo)`.
+    /// * `ncore`, `ncas` -
+    ///     number of frozen (doubly occupied) core orbitals, and number of active orbitals following them.
+    /// * `nelec` -
+    ///     number of active electrons; `ms2` is set to `nelec % 2`.
+    ///
+    /// Core Hamiltonian is evaluated by [`Self::hcore`] (including ECP); nuclear repulsion is evaluated by
+    /// [`Self::nuclear_repulsion_energy`]. Frozen core is folded into one-electron integrals and core energy.
+    /// Two-electron integrals are transformed by [`Self::integral_ao2mo`] over core and active orbitals.
+    ///
+    /// ```no_run
+    /// let mut fcidump = cint_data.fcidump(&coeff, 2, 8, 8);
+    /// fcidump.orbsym = Some(orbsym);
+    /// fcidump.write("FCIDUMP")?;
+    /// ```
+    pub fn fcidump(&self, coeff: &[f64], ncore: usize, ncas: usize, nelec: usize) -> FCIDump {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by FCIDUMP");
+        }
+        let nao = *self.cgto_loc().last().unwrap();
+        let nmo = Self::ao2mo_nmo(coeff, nao);
+        let norb = ncore + ncas;
+        if norb > nmo {
+            panic!("number of core and active orbitals {norb} exceeds number of MOs {nmo}");
+        }
+        let coeff = &coeff[..nao * norb];
+
+        let (hcore, _) = self.hcore();
+
+        // MO integrals of core and active orbitals
+        let h_mo = transform_pair(&hcore, nao, nao, coeff, norb, coeff, norb, false, &mut vec![0.; nao * norb]);
+        let (eri, _) = self.integral_ao2mo([coeff; 4]);
+        let npair_full = norb * (norb + 1) / 2;
+        let pair = |p: usize, q: usize| if p <= q { p + q * (q + 1) / 2 } else { q + p * (p + 1) / 2 };
+        let eri_mo = |p: usize, q: usize, r: usize, s: usize| eri[pair(p, q) + npair_full * pair(r, s)];
+
+        // fold frozen core
+        let mut ecore = self.nuclear_repulsion_energy();
+        for c in 0..ncore {
+            ecore += 2. * h_mo[c + norb * c];
+            for d in 0..ncore {
+                ecore += 2. * eri_mo(c, c, d, d) - eri_mo(c, d, d, c);
+            }
+        }
+        // active pairs (p <= q) in order of packed index, as indices of core and active orbitals
+        let act_pairs = (ncore..norb).flat_map(|q| (ncore..(q + 1)).map(move |p| (p, q))).collect::<Vec<_>>();
+        let h1e = act_pairs.iter().map(|&(p, q)| {
+            h_mo[p + norb * q] + (0..ncore).map(|c| 2. * eri_mo(p, q, c, c) - eri_mo(p, c, c, q)).sum::<f64>()
+        }).collect();
+        let h2e = act_pairs.iter().flat_map(|&(r, s)| act_pairs.iter().map(move |&(p, q)| (p, q, r, s)))
+            .map(|(p, q, r, s)| eri_mo(p, q, r, s)).collect();
+
+        FCIDump { norb: ncas, nelec, ms2: nelec % 2, orbsym: None, isym: 1, ecore, h1e, h2e }
+    }
+}
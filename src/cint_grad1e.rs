@@ -0,0 +1,176 @@
+use crate::cint_wrapper::*;
+use crate::cecp_wrapper::*;
+use crate::cint::{ATOM_OF, BAS_SLOTS};
+use crate::{CintType, CINTR2CDATA};
+
+impl CINTR2CDATA {
+    /// Atom index of each atomic orbital.
+    pub(crate) fn ao_atoms(&self) -> Vec<usize> {
+        let cgto_loc = self.cgto_loc();
+        let mut out = vec![0; *cgto_loc.last().unwrap()];
+        for shl in 0..self.c_nbas as usize {
+            let atm_id = self.c_bas[shl * BAS_SLOTS as usize + ATOM_OF as usize] as usize;
+            out[cgto_loc[shl]..cgto_loc[shl + 1]].iter_mut().for_each(|v| *v = atm_id);
+        }
+        return out;
+    }
+
+    /// Whether atom `atm_id` carries ECP.
+    fn atom_has_ecp(&self, atm_id: usize) -> bool {
+        self.c_ecp.chunks(BAS_SLOTS as usize).any(|bas| bas[ATOM_OF as usize] as usize == atm_id)
+    }
+
+    /// `-(<∇i|T + V_nuc (+ V_ECP)|j>)`, f-contiguous `(nao, nao, 3)`.
+    fn hcore_deriv_ip(&self) -> Vec<f64> {
+        let (mut out, _) = self.integral_s1::<int1e_ipkin>(None);
+        let (nuc, _) = self.integral_s1::<int1e_ipnuc>(None);
+        out.iter_mut().zip(nuc).for_each(|(h, v)| *h += v);
+        if self.c_necp > 0 {
+            let (ecp, _) = self.integral_ecp_s1::<ECPscalar_ipnuc>(None);
+            out.iter_mut().zip(ecp).for_each(|(h, v)| *h += v);
+        }
+        out.iter_mut().for_each(|h| *h = -*h);
+        return out;
+    }
+
+    /// `<∇i|-Z_A / |r - R_A| (+ V_ECP,A)|j>` with rinv origin at atom `atm_id`, f-contiguous `(nao, nao, 3)`.
+    fn hcore_deriv_rinv(&self, atm_id: usize) -> Vec<f64> {
+        let cint_data = self.with_rinv_at_nucleus(atm_id);
+        let charge = self.atom_charges()[atm_id];
+        let (mut out, _) = cint_data.integral_s1::<int1e_iprinv>(None);
+        out.iter_mut().for_each(|v| *v *= -charge);
+        if self.atom_has_ecp(atm_id) {
+            let (ecp, _) = cint_data.integral_ecp_s1::<ECPscalar_iprinv>(None);
+            out.iter_mut().zip(ecp).for_each(|(h, v)| *h += v);
+        }
+        return out;
+    }
+
+    fn check_grad1e_type(&self) {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by one-electron derivative assembly");
+        }
+    }
+
+    /// Generator of core Hamiltonian derivatives (see [`Self::hcore`]) with respect to coordinates of each atom.
+    ///
+    /// This is the same to PySCF's `hcore_generator`: `<∇i|h|j>` of all AOs is evaluated once when the generator is
+    /// created; calling the generator with `atm_id` adds the rinv-at-nucleus term (nuclear attraction and ECP of this
+    /// atom) to `<∇i|h|j>` of AOs on this atom, and symmetrizes. Use this instead of [`Self::hcore_deriv`] when
+    /// derivatives of more than one atom are required.
+    ///
+    /// Generator output is f-contiguous with shape `(nao, nao, 3)`; each component is a symmetric matrix.
+    ///
+    /// ```no_run
+    /// let hcore_deriv = cint_data.hcore_generator();
+    /// for atm_id in 0..natm {
+    ///     let (h1, shape) = hcore_deriv(atm_id);
+    /// }
+    /// ```
+    pub fn hcore_generator(&self) -> impl Fn(usize) -> (Vec<f64>, Vec<usize>) + '_ {
+        self.check_grad1e_type();
+        let h1 = self.hcore_deriv_ip();
+        let ao_atoms = self.ao_atoms();
+        move |atm_id| {
+            let nao = ao_atoms.len();
+            let mut v = self.hcore_deriv_rinv(atm_id);
+            for t in 0..3 {
+                for j in 0..nao {
+                    for i in (0..nao).filter(|&i| ao_atoms[i] == atm_id) {
+                        v[i + nao * j + nao * nao * t] += h1[i + nao * j + nao * nao * t];
+                    }
+                }
+            }
+            let out = (0..3 * nao * nao).map(|idx| {
+                let (i, j, t) = (idx % nao, (idx / nao) % nao, idx / (nao * nao));
+                v[i + nao * j + nao * nao * t] + v[j + nao * i + nao * nao * t]
+            }).collect();
+            (out, vec![nao, nao, 3])
+        }
+    }
+
+    /// Derivative of core Hamiltonian with respect to coordinates of one atom `atm_id`.
+    ///
+    /// This evaluates `<∇i|h|j>` of all AOs on each call; see [`Self::hcore_generator`] for derivatives of several
+    /// atoms. Output is f-contiguous with shape `(nao, nao, 3)`; each component is a symmetric matrix.
+    pub fn hcore_deriv(&self, atm_id: usize) -> (Vec<f64>, Vec<usize>) {
+        self.hcore_generator()(atm_id)
+    }
+
+    /// Derivative of overlap `S` with respect to coordinates of atom `atm_id`.
+    ///
+    /// Output is f-contiguous with shape `(nao, nao, 3)`; each component is a symmetric matrix.
+    pub fn ovlp_deriv(&self, atm_id: usize) -> (Vec<f64>, Vec<usize>) {
+        self.check_grad1e_type();
+        let (s1, _) = self.integral_s1::<int1e_ipovlp>(None);
+        let ao_atoms = self.ao_atoms();
+        let nao = ao_atoms.len();
+        // dS_ij = -<∇i|j> (i on atom) - <i|∇j> (j on atom)
+        let out = (0..3 * nao * nao).map(|idx| {
+            let (i, j, t) = (idx % nao, (idx / nao) % nao, idx / (nao * nao));
+            let mut v = 0.;
+            if ao_atoms[i] == atm_id { v -= s1[i + nao * j + nao * nao * t]; }
+            if ao_atoms[j] == atm_id { v -= s1[j + nao * i + nao * nao * t]; }
+            v
+        }).collect();
+        return (out, vec![nao, nao, 3]);
+    }
+
+    /// Gradient contribution `sum_ij D_ij dh_ij / dR_{A,t}` of core Hamiltonian, for all atoms.
+    ///
+    /// `dm` is f-contiguous density matrix `(nao, nao)`. Output is f-contiguous with shape `(3, natm)`, the same
+    /// layout as [`Self::nuclear_repulsion_gradient`]. This is equivalent to contracting [`Self::hcore_generator`] of
+    /// each atom, while `<∇i|h|j>` is evaluated only once and atoms are evaluated in parallel.
+    pub fn contract_hcore_deriv(&self, dm: &[f64]) -> (Vec<f64>, Vec<usize>) {
+        self.check_grad1e_type();
+        let ao_atoms = self.ao_atoms();
+        let nao = ao_atoms.len();
+        let natm = self.c_natm as usize;
+        if dm.len() != nao * nao {
+            panic!("size of density matrix {} is not the same to {nao} * {nao}", dm.len());
+        }
+        // D + D^T, since derivative matrices are (v + v^T)
+        let dm_sym = (0..nao * nao).map(|idx| dm[idx] + dm[(idx % nao) * nao + idx / nao]).collect::<Vec<f64>>();
+        let h1 = self.hcore_deriv_ip();
+
+        let mut out = vec![0.; 3 * natm];
+        for t in 0..3 {
+            for j in 0..nao {
+                for i in 0..nao {
+                    out[t + 3 * ao_atoms[i]] += dm_sym[i + nao * j] * h1[i + nao * j + nao * nao * t];
+                }
+            }
+        }
+        let grad_rinv = self.parallelism.map_collect(natm, |atm_id| {
+            let v = self.hcore_deriv_rinv(atm_id);
+            [0, 1, 2].map(|t| dm_sym.iter().zip(&v[nao * nao * t..nao * nao * (t + 1)]).map(|(d, v)| d * v).sum::<f64>())
+        });
+        grad_rinv.iter().enumerate().for_each(|(atm_id, g)| (0..3).for_each(|t| out[t + 3 * atm_id] += g[t]));
+        return (out, vec![3, natm]);
+    }
+
+    /// Gradient contribution `sum_ij W_ij dS_ij / dR_{A,t}` of overlap, for all atoms.
+    ///
+    /// `dm` is f-contiguous (usually energy-weighted) density matrix `(nao, nao)`; for SCF gradient, this
+    /// contribution is subtracted. Output is f-contiguous with shape `(3, natm)`.
+    pub fn contract_ovlp_deriv(&self, dm: &[f64]) -> (Vec<f64>, Vec<usize>) {
+        self.check_grad1e_type();
+        let ao_atoms = self.ao_atoms();
+        let nao = ao_atoms.len();
+        let natm = self.c_natm as usize;
+        if dm.len() != nao * nao {
+            panic!("size of density matrix {} is not the same to {nao} * {nao}", dm.len());
+        }
+        let (s1, _) = self.integral_s1::<int1e_ipovlp>(None);
+        let mut out = vec![0.; 3 * natm];
+        for t in 0..3 {
+            for j in 0..nao {
+                for i in 0..nao {
+                    let d = dm[i + nao * j] + dm[j + nao * i];
+                    out[t + 3 * ao_atoms[i]] -= d * s1[i + nao * j + nao * nao * t];
+                }
+            }
+        }
+        return (out, vec![3, natm]);
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::Mutex;
+use itertools::Itertools;
+use crate::cint_wrapper::*;
+use crate::cint::{ATOM_OF, BAS_SLOTS};
+use crate::cint_parallel::ThreadBuffers;
+use crate::cint_schedule::schedule_by_cost;
+use crate::{CintType, CINTR2CDATA};
+
+impl CINTR2CDATA {
+    /// Schwarz factors `Q_ij = sqrt(max |(ij|ij)|)` of shell pairs, f-contiguous `(nbas, nbas)`.
+    pub fn schwarz_shell_pairs(&self) -> Vec<f64> {
+        self.schwarz_shell_pairs_inner::<int2e>(true, &[0])
+    }
+
+    /// Schwarz factors `Q'_ij = sqrt(max_t max |(∇_t i j|∇_t i j)|)` (by `int2e_ip1ip2`) of shell pairs,
+    /// f-contiguous `(nbas, nbas)`; not symmetric.
+    ///
+    /// Together with [`Self::schwarz_shell_pairs`], this bounds derivative integrals: `|(∇_t i j|kl)| <= Q'_ij Q_kl`.
+    pub fn schwarz_shell_pairs_ip1(&self) -> Vec<f64> {
+        // diagonal components xx, yy, zz of (∇i j|∇k l)
+        self.schwarz_shell_pairs_inner::<int2e_ip1ip2>(false, &[0, 4, 8])
+    }
+
+    /// `sqrt(max |(ij|ij)|)` over components `comps` of `T`, of shell pairs `(i, j)` (only `i <= j` if `symmetric`).
+    fn schwarz_shell_pairs_inner<T>(&self, symmetric: bool, comps: &[usize]) -> Vec<f64>
+    where
+        T: Integrator
+    {
+        let nbas = self.c_nbas as usize;
+        let shl_slices = vec![[0, self.c_nbas]; 4];
+        let ctx = self.eval_context::<T, f64>();
+        let cache_size = ctx.size_of_cache::<T>(&shl_slices);
+        let buf_size = T::n_comp() * (0..self.c_nbas).map(|shl| ctx.cgto_size(shl)).max().unwrap_or(0).pow(4);
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
+        let thread_buf = ThreadBuffers::new(&self.parallelism, buf_size, 0.);
+        let ij_pairs = match symmetric {
+            true => (0..nbas).flat_map(|j| (0..(j + 1)).map(move |i| (i, j))).collect_vec(),
+            false => (0..nbas).cartesian_product(0..nbas).collect_vec(),
+        };
+        let q_pairs = self.parallelism.map_collect(ij_pairs.len(), |idx| {
+            let (i, j) = ij_pairs[idx];
+            let shls = [i as i32, j as i32, i as i32, j as i32];
+            let size = shls.iter().map(|&shl| ctx.cgto_size(shl)).product::<usize>();
+            let mut cache = thread_cache.get();
+            let mut buf = thread_buf.get();
+            unsafe { ctx.integral_block::<f64>(&mut buf, &shls, &[], &mut cache); }
+            let vmax = comps.iter().flat_map(|&c| &buf[size * c..size * (c + 1)]).fold(0., |acc: f64, v| acc.max(v.abs()));
+            (i, j, vmax.sqrt())
+        });
+        let mut out = vec![0.; nbas * nbas];
+        q_pairs.into_iter().for_each(|(i, j, q)| {
+            out[i + nbas * j] = q;
+            if symmetric { out[j + nbas * i] = q; }
+        });
+        return out;
+    }
+
+    /// Direct gradient contraction of two-electron integrals (Coulomb and exchange), without storing `int2e_ip1`.
+    ///
+    /// For each (symmetric, f-contiguous `(nao, nao)`) density matrix `D` in `dms`, this gives
+    /// - Coulomb: `sum_ijkl D_ij D_kl d(ij|kl)/dR_{A,t}`,
+    /// - exchange: `sum_ijkl D_ik D_jl d(ij|kl)/dR_{A,t}`.
+    ///
+    /// For RHF (`D` of both spins), the two-electron gradient is `grad_j / 2 - grad_k / 4`; for UHF, pass total
+    /// density for Coulomb and spin densities for exchange (`grad_j[total] / 2 - (grad_k[alpha] + grad_k[beta]) / 2`).
+    ///
+    /// Shell quartets of `int2e_ip1` are iterated in parallel (tasks of shell pairs `kl`, expensive ones first,
+    /// with thread-local cache and buffer as in [`Self::integral_s1`]). Quartets are skipped if Schwarz bound
+    /// `Q'_ij Q_kl` of derivative integrals ([`Self::schwarz_shell_pairs_ip1`], [`Self::schwarz_shell_pairs`])
+    /// times maximum density elements involved is smaller than `screen_threshold` (0 for no screening), the same to
+    /// PySCF's `CVHFgrad_jk_prescreen`.
+    ///
+    /// Output `(grad_j, grad_k, shape)` is f-contiguous with shape `(3, natm, n_dm)`; `grad_j` (or `grad_k`) is
+    /// empty if `with_j` (or `with_k`) is false.
+    pub fn grad_jk(&self, dms: &[&[f64]], with_j: bool, with_k: bool, screen_threshold: f64) -> (Vec<f64>, Vec<f64>, Vec<usize>) {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by two-electron gradient contraction");
+        }
+        let nbas = self.c_nbas as usize;
+        let natm = self.c_natm as usize;
+        let n_dm = dms.len();
+        let cgto_loc = self.cgto_loc();
+        let nao = *cgto_loc.last().unwrap();
+        dms.iter().for_each(|dm| if dm.len() != nao * nao {
+            panic!("size of density matrix {} is not the same to {nao} * {nao}", dm.len());
+        });
+        let out_shape = vec![3, natm, n_dm];
+        if !with_j && !with_k {
+            return (vec![], vec![], out_shape);
+        }
+
+        // screening: Schwarz factors of (∇i j| and |kl), and maximum density elements of shell blocks
+        let (q_cond_ip1, q_cond) = match screen_threshold > 0. {
+            true => (self.schwarz_shell_pairs_ip1(), self.schwarz_shell_pairs()),
+            false => (vec![f64::INFINITY; nbas * nbas], vec![f64::INFINITY; nbas * nbas]),
+        };
+        let dm_cond = (0..nbas * nbas).map(|idx| {
+            let (shl_i, shl_j) = (idx % nbas, idx / nbas);
+            (cgto_loc[shl_i]..cgto_loc[shl_i + 1]).cartesian_product(cgto_loc[shl_j]..cgto_loc[shl_j + 1])
+                .flat_map(|(i, j)| dms.iter().map(move |dm| dm[i + nao * j].abs()))
+                .fold(0., f64::max)
+        }).collect_vec();
+        let atom_of = (0..nbas).map(|shl| self.c_bas[shl * BAS_SLOTS as usize + ATOM_OF as usize] as usize).collect_vec();
+
+        // thread-local cache and buffer
+        let shl_slices = vec![[0, self.c_nbas]; 4];
+        let ctx = self.eval_context::<int2e_ip1, f64>();
+        let cache_size = ctx.size_of_cache::<int2e_ip1>(&shl_slices);
+        let buf_size = 3 * (0..self.c_nbas).map(|shl| ctx.cgto_size(shl)).max().unwrap_or(0).pow(4);
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
+        let thread_buf = ThreadBuffers::new(&self.parallelism, buf_size, 0.);
+
+        // tasks: shell pairs k <= l
+        let kl_pairs = (0..nbas).flat_map(|l| (0..(l + 1)).map(move |k| (k, l))).collect_vec();
+        let task_costs = kl_pairs.iter().map(|&(k, l)| self.shell_tuple_cost(&[k as i32, l as i32])).collect_vec();
+        let task_order = schedule_by_cost(&task_costs);
+
+        let size_out = 3 * natm * n_dm;
+        let result = Mutex::new((vec![0.; size_out], vec![0.; size_out]));
+        self.parallelism.for_each_ordered(&task_order, |idx_task| {
+            let (shl_k, shl_l) = kl_pairs[idx_task];
+            let mut cache = thread_cache.get();
+            let mut buf = thread_buf.get();
+            let mut grad_j = vec![0.; size_out];
+            let mut grad_k = vec![0.; size_out];
+            let (k0, k1, l0, l1) = (cgto_loc[shl_k], cgto_loc[shl_k + 1], cgto_loc[shl_l], cgto_loc[shl_l + 1]);
+            let (dk, dl) = (k1 - k0, l1 - l0);
+
+            for (shl_i, shl_j) in (0..nbas).cartesian_product(0..nbas) {
+                // Schwarz screening with density
+                let d_j = if with_j { dm_cond[shl_i + nbas * shl_j] * dm_cond[shl_k + nbas * shl_l] } else { 0. };
+                let d_k = if with_k {
+                    f64::max(dm_cond[shl_i + nbas * shl_k] * dm_cond[shl_j + nbas * shl_l],
+                             dm_cond[shl_i + nbas * shl_l] * dm_cond[shl_j + nbas * shl_k])
+                } else { 0. };
+                if q_cond_ip1[shl_i + nbas * shl_j] * q_cond[shl_k + nbas * shl_l] * f64::max(d_j, d_k) < screen_threshold {
+                    continue;
+                }
+
+                let (i0, i1, j0, j1) = (cgto_loc[shl_i], cgto_loc[shl_i + 1], cgto_loc[shl_j], cgto_loc[shl_j + 1]);
+                let shls = [shl_i as i32, shl_j as i32, shl_k as i32, shl_l as i32];
+                unsafe { ctx.integral_block::<f64>(&mut buf, &shls, &[], &mut cache); }
+                let atm_id = atom_of[shl_i];
+                let size = (i1 - i0) * (j1 - j0) * dk * dl;
+
+                for (n, dm) in dms.iter().enumerate() {
+                    for t in 0..3 {
+                        let block = &buf[size * t..size * (t + 1)];
+                        let (mut val_j, mut val_k) = (0., 0.);
+                        let mut idx_block = 0;
+                        for l in l0..l1 {
+                            for k in k0..k1 {
+                                let d_kl = dm[k + nao * l];
+                                for j in j0..j1 {
+                                    let (d_jl, d_jk) = (dm[j + nao * l], dm[j + nao * k]);
+                                    for i in i0..i1 {
+                                        let v = block[idx_block];
+                                        idx_block += 1;
+                                        if with_j {
+                                            val_j += v * dm[i + nao * j] * d_kl;
+                                        }
+                                        if with_k {
+                                            val_k += v * match shl_k == shl_l {
+                                                true => dm[i + nao * k] * d_jl,
+                                                false => dm[i + nao * k] * d_jl + dm[i + nao * l] * d_jk,
+                                            };
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        // (∇i j|kl) = -d/dR_A for i on atom A; (kl) and (lk) both counted for k < l
+                        let fac_j = if shl_k == shl_l { -4. } else { -8. };
+                        grad_j[t + 3 * atm_id + 3 * natm * n] += fac_j * val_j;
+                        grad_k[t + 3 * atm_id + 3 * natm * n] -= 4. * val_k;
+                    }
+                }
+            }
+
+            let mut result = result.lock().unwrap();
+            result.0.iter_mut().zip(grad_j).for_each(|(r, g)| *r += g);
+            result.1.iter_mut().zip(grad_k).for_each(|(r, g)| *r += g);
+        });
+
+        let (grad_j, grad_k) = result.into_inner().unwrap();
+        let grad_j = if with_j { grad_j } else { vec![] };
+        let grad_k = if with_k { grad_k } else { vec![] };
+        return (grad_j, grad_k, out_shape);
+    }
+}
@@ -0,0 +1,432 @@
+use std::ptr::{null, null_mut};
+use std::sync::Mutex;
+use itertools::Itertools;
+use rayon::prelude::*;
+use crate::cint_wrapper::*;
+use crate::cint::{self, NGRIDS, PTR_GRIDS, PTR_ENV_START};
+use crate::{CintType, CINTR2CDATA};
+use crate::utilities::*;
+use crate::cint_context::CINTEvalContext;
+use crate::cint_parallel::ThreadBuffers;
+use crate::cint_schedule::schedule_by_cost;
+use num_complex::*;
+
+/// Number of grid points evaluated by one call to libcint `int1e_grids` series of integrators.
+///
+/// Grids are batched into chunks of this size; each chunk with one shell pair is one parallel task.
+/// This bounds the cache size required by each thread, regardless of number of grids.
+pub const GRIDS_BATCH_SIZE: usize = 208;
+
+impl CINTR2CDATA {
+
+    /* #region grids in env */
+
+    /// Store coordinates of grids (in Bohr) into `c_env`, for `int1e_grids` series of integrators.
+    ///
+    /// Grids are appended to the end of `c_env`, and `c_env[NGRIDS]`, `c_env[PTR_GRIDS]` are updated.
+    /// Grids stored by previous call of this function will be replaced.
+    pub fn set_grids(&mut self, grids: &[[f64; 3]]) {
+        // remove previous grids if they are placed at the end of `c_env`
+        let ngrids_old = self.c_env[NGRIDS as usize] as usize;
+        let ptr_old = self.c_env[PTR_GRIDS as usize] as usize;
+        if ptr_old >= PTR_ENV_START as usize && ptr_old + 3 * ngrids_old == self.c_env.len() {
+            self.c_env.truncate(ptr_old);
+        }
+        self.c_env[NGRIDS as usize] = grids.len() as f64;
+        self.c_env[PTR_GRIDS as usize] = self.c_env.len() as f64;
+        self.c_env.extend(grids.iter().flatten());
+    }
+
+    /// Number of grids stored in `c_env`.
+    pub fn ngrids(&self) -> usize {
+        self.c_env[NGRIDS as usize] as usize
+    }
+
+    /// Coordinates of grids stored in `c_env`.
+    pub fn get_grids(&self) -> Vec<[f64; 3]> {
+        let ngrids = self.ngrids();
+        let ptr = self.c_env[PTR_GRIDS as usize] as usize;
+        self.c_env[ptr..ptr + 3 * ngrids].chunks(3).map(|x| [x[0], x[1], x[2]]).collect()
+    }
+
+    /* #endregion */
+
+    /* #region grids sanity check and cache size */
+
+    pub fn check_grids_integrator<T> (&self) -> Result<(), String>
+    where
+        T: Integrator
+    {
+        if !T::name().starts_with("int1e_grids") {
+            return Err(format!("integrator {} is not one of `int1e_grids` series", T::name()));
+        }
+        if self.ngrids() == 0 {
+            return Err("No grids found in `c_env`; grids should be set before calling grids integrator.".to_string());
+        }
+        return Ok(());
+    }
+
+    /// Obtain cache size for `int1e_grids` series of integrators.
+    ///
+    /// Unlike [`Self::size_of_cache`], shell indices passed to libcint contain grid range
+    /// (`shls[2]..shls[3]`), which is taken to be at most [`GRIDS_BATCH_SIZE`].
+    pub fn size_of_cache_grids<T> (&self, shl_slices: &[[i32; 2]]) -> usize
+    where
+        T: Integrator
+    {
+        let shls_min = shl_slices.iter().map(|x| x[0]).min().unwrap_or(0);
+        let shls_max = shl_slices.iter().map(|x| x[1]).max().unwrap_or(self.c_nbas);
+        let ngrids_batch = GRIDS_BATCH_SIZE.min(self.ngrids()) as i32;
+        (shls_min..shls_max).map(|shl| {
+            let shls = [shl, shl, 0, ngrids_batch];
+            match self.cint_type {
+                CintType::Spheric => unsafe {
+                    T::integral_sph(
+                        null_mut(), null(), shls.as_ptr(),
+                        self.c_atm.as_ptr(), self.c_natm,
+                        self.c_bas.as_ptr(), self.c_nbas,
+                        self.c_env.as_ptr(), null(), null_mut()) as usize
+                    },
+                CintType::Cartesian => unsafe {
+                    T::integral_cart(
+                        null_mut(), null(), shls.as_ptr(),
+                        self.c_atm.as_ptr(), self.c_natm,
+                        self.c_bas.as_ptr(), self.c_nbas,
+                        self.c_env.as_ptr(), null(), null_mut()) as usize
+                    },
+                CintType::Spinor => unsafe {
+                    T::integral_spinor(
+                        null_mut(), null(), shls.as_ptr(),
+                        self.c_atm.as_ptr(), self.c_natm,
+                        self.c_bas.as_ptr(), self.c_nbas,
+                        self.c_env.as_ptr(), null(), null_mut()) as usize
+                    },
+            }
+        }).max().unwrap_or(0)
+    }
+
+    /* #endregion */
+
+    /// Main integral engine for `int1e_grids` series of integrators (s1 symmetry).
+    ///
+    /// Grids should have been stored in `c_env` by [`Self::set_grids`].
+    /// Output is f-contiguous with shape `(grid, i, j, comp)`.
+    ///
+    /// This function a low-level API, which is not intended to be called by user.
+    pub fn integral_grids_s1_inplace<T, F> (&self, out: &mut [F], shl_slices: &[[i32; 2]])
+    where
+        T: Integrator, F: FF64
+    {
+        self.eval_context::<T, F>().integral_grids_s1_inplace::<F>(out, shl_slices);
+    }
+
+    pub fn integral_grids_s1_inner<T, F> (&self, grids: &[[f64; 3]], shl_slices: Option<&[[i32; 2]]>) -> (Vec<F>, Vec<usize>)
+    where
+        T: Integrator, F: FF64
+    {
+        // grids are stored in a copy of `c_env`; `self` is not modified
+        let mut cint_data = self.clone_without_optimizer();
+        cint_data.set_grids(grids);
+        let ctx = cint_data.eval_context::<T, F>();
+        // specify shl_slices
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices,
+            None => &vec![[0, self.c_nbas]; T::n_center()],
+        };
+        // specify and allocate output
+        let mut out_shape = [vec![grids.len()], ctx.cgto_shape::<T>(shl_slices)].concat();
+        if T::n_comp() > 1 { out_shape.push(T::n_comp()); }
+        let out_size = out_shape.iter().product::<usize>();
+        let mut out = vec![F::zero(); out_size];
+        // main integral engine
+        if out_size > 0 {
+            ctx.integral_grids_s1_inplace::<F>(&mut out, shl_slices);
+        }
+        return (out, out_shape);
+    }
+
+    /// Integral of `int1e_grids` series on given grids, with no symmetry (s1).
+    ///
+    /// * `grids` -
+    ///     Coordinates of grids (in Bohr), with shape (N, 3).
+    /// * `shl_slices` -
+    ///     Shell slices of the two atomic orbital indices `i`, `j`.
+    ///
+    /// Output tensor is f-contiguous with shape `(grid, i, j)`, or `(grid, i, j, comp)` if the
+    /// integrator has more than one component.
+    ///
+    /// ```no_run
+    /// let grids = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+    /// let (out, out_shape) = cint_data.integral_grids_s1::<int1e_grids_ip>(&grids, None);
+    /// // out_shape: [2, nao, nao, 3]
+    /// ```
+    pub fn integral_grids_s1<T> (&self, grids: &[[f64; 3]], shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
+    where
+        T: Integrator
+    {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor should be called by `integral_grids_spinor_s1<Integrator>` or `integral_grids_s1_inner<Integrator, Complex<f64>>`");
+        }
+        return self.integral_grids_s1_inner::<T, f64>(grids, shl_slices);
+    }
+
+    pub fn integral_grids_spinor_s1<T> (&self, grids: &[[f64; 3]], shl_slices: Option<&[[i32; 2]]>) -> (Vec<Complex<f64>>, Vec<usize>)
+    where
+        T: Integrator
+    {
+        return self.integral_grids_s1_inner::<T, Complex<f64>>(grids, shl_slices);
+    }
+
+    /* #region electrostatic potential and field */
+
+    /// Contract integrals of `int1e_grids` series with AO density matrix, without forming the full
+    /// `(grid, i, j, comp)` tensor.
+    ///
+    /// * `grids` -
+    ///     Coordinates of grids (in Bohr), with shape (N, 3).
+    /// * `dm` -
+    ///     AO density matrix, f-contiguous with shape (nao, nao).
+    ///
+    /// Output is f-contiguous with shape `(grid, comp)`, where
+    /// `out[g, t] = sum_{ij} dm[i, j] * (i | O_t(g) | j)`.
+    ///
+    /// Each parallel task handles one batch of [`GRIDS_BATCH_SIZE`] grids over all shell pairs,
+    /// so memory usage is bounded by the batch size instead of number of grids.
+    pub fn integral_grids_contract_dm<T> (&self, grids: &[[f64; 3]], dm: &[f64]) -> Vec<f64>
+    where
+        T: Integrator
+    {
+        // grids are stored in a copy of `c_env`; `self` is not modified
+        let mut cint_data = self.clone_without_optimizer();
+        cint_data.set_grids(grids);
+        let ctx = cint_data.eval_context::<T, f64>();
+        ctx.check_grids_integrator::<T>().unwrap();
+        ctx.check_float_type::<T, f64>().unwrap();
+
+        let n_comp = T::n_comp();
+        let ngrids = grids.len();
+        let cgto_loc = ctx.cgto_loc();
+        let nao = self.nao_of_dm(dm);
+
+        let cache_size = ctx.size_of_cache_grids::<T>(&[]);
+        let cgto_max = (0..self.c_nbas).map(|shl| ctx.cgto_size(shl)).max().unwrap_or(0);
+        let buf_size = GRIDS_BATCH_SIZE.min(ngrids) * cgto_max * cgto_max * n_comp;
+        let n_batch = ngrids.div_ceil(GRIDS_BATCH_SIZE);
+
+        let out_batches = ctx.parallelism.map_collect(n_batch, |idx_b| {
+            let grid_start = idx_b * GRIDS_BATCH_SIZE;
+            let grid_stop = (grid_start + GRIDS_BATCH_SIZE).min(ngrids);
+            let nbatch = grid_stop - grid_start;
+            let mut cache = vec![0.; cache_size];
+            let mut buf = vec![0.; buf_size];
+            let mut out = vec![0.; nbatch * n_comp];
+            for shl_j in 0..self.c_nbas {
+                for shl_i in 0..self.c_nbas {
+                    let shls = [shl_i, shl_j, grid_start as i32, grid_stop as i32];
+                    unsafe { ctx.integral_block::<f64>(&mut buf, &shls, &[], &mut cache); }
+                    // buffer is f-contiguous (grid, i, j, comp)
+                    let di = ctx.cgto_size(shl_i);
+                    let dj = ctx.cgto_size(shl_j);
+                    let ao_i = cgto_loc[shl_i as usize];
+                    let ao_j = cgto_loc[shl_j as usize];
+                    for c in 0..n_comp {
+                        for j in 0..dj {
+                            for i in 0..di {
+                                let d = dm[(ao_i + i) + nao * (ao_j + j)];
+                                let buf_offset = nbatch * (i + di * (j + dj * c));
+                                let buf_slice = &buf[buf_offset..buf_offset + nbatch];
+                                let out_slice = &mut out[nbatch * c..nbatch * (c + 1)];
+                                out_slice.iter_mut().zip(buf_slice).for_each(|(o, b)| *o += d * b);
+                            }
+                        }
+                    }
+                }
+            }
+            out
+        });
+
+        // gather batches into f-contiguous (grid, comp)
+        let mut out = vec![0.; ngrids * n_comp];
+        for (idx_b, out_batch) in out_batches.iter().enumerate() {
+            let grid_start = idx_b * GRIDS_BATCH_SIZE;
+            let nbatch = out_batch.len() / n_comp;
+            for c in 0..n_comp {
+                out[grid_start + ngrids * c..grid_start + ngrids * c + nbatch]
+                    .copy_from_slice(&out_batch[nbatch * c..nbatch * (c + 1)]);
+            }
+        }
+        return out;
+    }
+
+    /// Number of AOs, checked against size of density matrix `(nao, nao)`.
+    fn nao_of_dm(&self, dm: &[f64]) -> usize {
+        let nao = *self.cgto_loc().last().unwrap();
+        if dm.len() != nao * nao {
+            panic!("size of density matrix {} is not the same to {nao} * {nao}", dm.len());
+        }
+        return nao;
+    }
+
+    /// Electrostatic potential of nuclei (point charges) on grids.
+    ///
+    /// Output has length of number of grids.
+    pub fn nuclear_potential_on_grids(&self, grids: &[[f64; 3]]) -> Vec<f64> {
+        let coords = self.atom_coords();
+        let charges = self.atom_charges();
+        self.parallelism.map_collect(grids.len(), |idx_g| {
+            let g = &grids[idx_g];
+            coords.iter().zip(&charges).map(|(r, z)| {
+                let dist = ((g[0] - r[0]).powi(2) + (g[1] - r[1]).powi(2) + (g[2] - r[2]).powi(2)).sqrt();
+                z / dist
+            }).sum::<f64>()
+        })
+    }
+
+    /// Electric field of nuclei (point charges) on grids.
+    ///
+    /// Output is f-contiguous with shape `(grid, 3)`.
+    pub fn nuclear_field_on_grids(&self, grids: &[[f64; 3]]) -> Vec<f64> {
+        let coords = self.atom_coords();
+        let charges = self.atom_charges();
+        let ngrids = grids.len();
+        let field = self.parallelism.map_collect(grids.len(), |idx_g| {
+            let g = &grids[idx_g];
+            let mut e = [0.; 3];
+            coords.iter().zip(&charges).for_each(|(r, z)| {
+                let d = [g[0] - r[0], g[1] - r[1], g[2] - r[2]];
+                let dist = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+                let fac = z / (dist * dist * dist);
+                (0..3).for_each(|t| e[t] += fac * d[t]);
+            });
+            e
+        });
+        (0..3).flat_map(|t| field.iter().map(move |e| e[t])).collect()
+    }
+
+    /// Molecular electrostatic potential on grids, including nuclear and electronic contributions.
+    ///
+    /// * `grids` -
+    ///     Coordinates of grids (in Bohr), with shape (N, 3).
+    /// * `dm` -
+    ///     AO density matrix, f-contiguous with shape (nao, nao).
+    ///
+    /// Nuclei are treated as point charges (`atom_charges`), even if finite nuclear model is set in `c_atm`.
+    /// Output has length of number of grids.
+    pub fn electrostatic_potential(&self, grids: &[[f64; 3]], dm: &[f64]) -> Vec<f64> {
+        let elec = self.integral_grids_contract_dm::<int1e_grids>(grids, dm);
+        let nuc = self.nuclear_potential_on_grids(grids);
+        nuc.iter().zip(&elec).map(|(n, e)| n - e).collect()
+    }
+
+    /// Molecular electric field on grids, including nuclear and electronic contributions.
+    ///
+    /// * `grids` -
+    ///     Coordinates of grids (in Bohr), with shape (N, 3).
+    /// * `dm` -
+    ///     AO density matrix, f-contiguous with shape (nao, nao); not required to be symmetric.
+    ///
+    /// Nuclei are treated as point charges (`atom_charges`), even if finite nuclear model is set in `c_atm`.
+    /// Output is f-contiguous with shape `(grid, 3)`.
+    pub fn electric_field(&self, grids: &[[f64; 3]], dm: &[f64]) -> Vec<f64> {
+        let nao = self.nao_of_dm(dm);
+        // With A_i, A_j centers of i, j, translational invariance gives
+        //     d/dg (i|1/|r-g||j) = - d/dA_i (i|1/|r-g||j) - d/dA_j (i|1/|r-g||j)
+        //                        = (\nabla i|1/|r-g||j) + (i|1/|r-g||\nabla j),
+        // where \nabla (as in `int1e_grids_ip`) acts on electron coordinate, so that \nabla i = - d/dA_i i.
+        // Electronic field sum_ij D_ij d/dg (i|1/|r-g||j) is then contracted from `int1e_grids_ip` with D + D^T.
+        let dm_sym = (0..nao * nao).map(|ij| dm[ij] + dm[(ij % nao) * nao + ij / nao]).collect_vec();
+        let elec = self.integral_grids_contract_dm::<int1e_grids_ip>(grids, &dm_sym);
+        let nuc = self.nuclear_field_on_grids(grids);
+        nuc.iter().zip(&elec).map(|(n, e)| n + e).collect()
+    }
+
+    /* #endregion */
+}
+
+impl<T> CINTEvalContext<'_, T>
+where
+    T: Integrator
+{
+    /// Main integral engine for `int1e_grids` series of integrators (s1 symmetry).
+    ///
+    /// Grids should have been stored in `c_env` by [`CINTR2CDATA::set_grids`].
+    /// Output is f-contiguous with shape `(grid, i, j, comp)`.
+    ///
+    /// This function a low-level API, which is not intended to be called by user.
+    pub fn integral_grids_s1_inplace<F> (&self, out: &mut [F], shl_slices: &[[i32; 2]])
+    where
+        F: FF64
+    {
+        /* #region 1. dimension definition and sanity check */
+
+        self.check_grids_integrator::<T>().unwrap();
+        self.check_float_type::<T, F>().unwrap();
+        self.check_shl_slices::<T>(shl_slices).unwrap();
+
+        let ngrids = self.ngrids();
+        let cgto_shape = self.cgto_shape::<T>(shl_slices);
+        let grids_shape_i32 = [ngrids as i32, cgto_shape[0] as i32, cgto_shape[1] as i32];
+        let index_shape = shl_slices.iter().map(|[shl_start, shl_stop]| (shl_stop - shl_start) as usize).collect_vec();
+        let cgto_locs_rel = self.cgto_loc_slices_relative(shl_slices);
+        let n_batch = ngrids.div_ceil(GRIDS_BATCH_SIZE);
+        let n_comp = T::n_comp();
+        let out_shape = [ngrids, cgto_shape[0], cgto_shape[1], n_comp];
+        let out_size = out_shape.iter().product::<usize>();
+        if out.len() < out_size {
+            panic!("size of output {} is smaller than required {out_size}", out.len());
+        }
+
+        /* #endregion */
+
+        /* #region 2. preparation for integral engine */
+
+        // cache: thread-local
+        let cache_size = self.size_of_cache_grids::<T>(shl_slices);
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
+
+        // out: shared by threads, each parallel task writes its own (disjoint) blocks
+        let out = DisjointMut::new(out);
+
+        /* #endregion */
+
+        /* #region 3. parallel integral generation */
+
+        // Each task (shell pair and grid batch) writes its own block; blocks of different tasks never overlap,
+        // so writing through `DisjointMut` is free of data racing.
+
+        // expensive shell pairs are dispatched first; grid batches of one shell pair have (nearly) the same cost
+        let task_costs = (0..(index_shape[1] * index_shape[0] * n_batch)).map(|idx_jib| {
+            let shl_j = (idx_jib / (index_shape[0] * n_batch)) as i32 + shl_slices[1][0];
+            let shl_i = ((idx_jib / n_batch) % index_shape[0]) as i32 + shl_slices[0][0];
+            self.shell_tuple_cost(&[shl_i, shl_j])
+        }).collect_vec();
+        let task_order = schedule_by_cost(&task_costs);
+
+        self.parallelism.for_each_ordered(&task_order, |idx_jib| {
+            let idx_j = idx_jib / (index_shape[0] * n_batch);
+            let idx_i = (idx_jib / n_batch) % index_shape[0];
+            let idx_b = idx_jib % n_batch;
+            let shl_i = idx_i as i32 + shl_slices[0][0];
+            let shl_j = idx_j as i32 + shl_slices[1][0];
+            let cgto_i = cgto_locs_rel[0][idx_i];
+            let cgto_j = cgto_locs_rel[1][idx_j];
+            let grid_start = idx_b * GRIDS_BATCH_SIZE;
+            let grid_stop = (grid_start + GRIDS_BATCH_SIZE).min(ngrids);
+
+            let mut cache = thread_cache.get();
+
+            let shls = [shl_i, shl_j, grid_start as i32, grid_stop as i32];
+            let offset = grid_start + ngrids * (cgto_i + cgto_shape[0] * cgto_j);
+            let block_shape = [
+                grid_stop - grid_start,
+                cgto_locs_rel[0][idx_i + 1] - cgto_i,
+                cgto_locs_rel[1][idx_j + 1] - cgto_j,
+                n_comp];
+            let extent = block_extent(&block_shape, &out_shape);
+
+            unsafe { self.integral_block_ptr::<F>(out.ptr_at(offset, extent), &shls, &grids_shape_i32, &mut cache); }
+        });
+
+        /* #endregion */
+    }
+}
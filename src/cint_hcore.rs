@@ -0,0 +1,100 @@
+use std::ops::Add;
+use num_complex::Complex;
+use crate::cint_wrapper::*;
+use crate::cecp_wrapper::*;
+use crate::cecp_crafter::{ECPData, ECPEvalContext};
+use crate::cint_parallel::ThreadBuffers;
+use crate::cint_schedule::{shell_tuple_cost, schedule_by_cost};
+use crate::utilities::*;
+use crate::{CintType, CINTR2CDATA};
+
+impl CINTR2CDATA {
+    /// Core Hamiltonian `H_core = T + V_nuc (+ V_ECP)` (spheric or cartesian, by GTO type of `self`).
+    ///
+    /// Kinetic (`int1e_kin`), nuclear attraction (`int1e_nuc`) and ECP (`ECPscalar`, if ECP exists) blocks are
+    /// evaluated and summed in one parallel pass over shell pairs. Nuclear attraction is evaluated by libcint, so
+    /// that nuclear model in `c_atm` (point charge or finite gaussian nucleus) is honored.
+    ///
+    /// Output is f-contiguous with shape `(nao, nao)`.
+    pub fn hcore(&self) -> (Vec<f64>, Vec<usize>) {
+        return self.hcore_inner::<f64>();
+    }
+
+    /// Core Hamiltonian `H_core = T + V_nuc` of spinor GTOs.
+    ///
+    /// ECP is not supported for spinor; this function panics if ECP exists. See also [`Self::hcore`].
+    pub fn hcore_spinor(&self) -> (Vec<Complex<f64>>, Vec<usize>) {
+        return self.hcore_inner::<Complex<f64>>();
+    }
+
+    fn hcore_inner<F> (&self) -> (Vec<F>, Vec<usize>)
+    where
+        F: FF64 + Add<Output = F> + From<f64>
+    {
+        let ctx_kin = self.eval_context::<int1e_kin, F>();
+        let ctx_nuc = self.eval_context::<int1e_nuc, F>();
+        let ecp_data = match (self.c_necp, ctx_kin.cint_type()) {
+            (0, _) => None,
+            (_, CintType::Spinor) => panic!("ECP is not supported for spinor core Hamiltonian"),
+            _ => Some(ECPData::from_cint_data(self)),
+        };
+        let ctx_ecp = ecp_data.as_ref().map(ECPEvalContext::<ECPscalar>::new);
+
+        let nbas = self.c_nbas;
+        let shl_slices = [[0, nbas]; 2];
+        let cgto_loc = ctx_kin.cgto_loc_slice_relative(&shl_slices[0]);
+        let nao = *cgto_loc.last().unwrap();
+        let max_size = (0..nbas).map(|shl| ctx_kin.cgto_size(shl)).max().unwrap_or(0);
+
+        // thread-local cache and buffers (kinetic and nuclear blocks; ECP block is always real)
+        let cache_size = [
+            ctx_kin.size_of_cache::<int1e_kin>(&shl_slices),
+            ctx_nuc.size_of_cache::<int1e_nuc>(&shl_slices),
+            ctx_ecp.as_ref().map_or(0, |ctx| ctx.size_of_cache::<ECPscalar>(&shl_slices)),
+        ].into_iter().max().unwrap();
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
+        let thread_buf = ThreadBuffers::new(&self.parallelism, 2 * max_size * max_size, F::zero());
+        let buf_ecp_size = if ctx_ecp.is_some() { max_size * max_size } else { 0 };
+        let thread_buf_ecp = ThreadBuffers::new(&self.parallelism, buf_ecp_size, 0.);
+
+        let mut out = vec![F::zero(); nao * nao];
+        let out_mut = DisjointMut::new(&mut out);
+
+        // expensive shell pairs are dispatched first
+        let n = nbas as usize;
+        let task_costs = (0..n * n).map(|idx| shell_tuple_cost(&self.c_bas, &[(idx % n) as i32, (idx / n) as i32])).collect::<Vec<f64>>();
+        let task_order = schedule_by_cost(&task_costs);
+
+        self.parallelism.for_each_ordered(&task_order, |idx| {
+            let (idx_i, idx_j) = (idx % n, idx / n);
+            let shls = [idx_i as i32, idx_j as i32];
+            let (di, dj) = (cgto_loc[idx_i + 1] - cgto_loc[idx_i], cgto_loc[idx_j + 1] - cgto_loc[idx_j]);
+            let size = di * dj;
+
+            let mut cache = thread_cache.get();
+            let mut buf = thread_buf.get();
+            let mut buf_ecp = thread_buf_ecp.get();
+            let (buf_kin, buf_nuc) = buf.split_at_mut(size);
+            unsafe {
+                ctx_kin.integral_block::<F>(buf_kin, &shls, &[], &mut cache);
+                ctx_nuc.integral_block::<F>(buf_nuc, &shls, &[], &mut cache);
+                if let Some(ctx) = &ctx_ecp {
+                    ctx.integral_block(&mut buf_ecp, &shls, &[], &mut cache);
+                }
+            }
+
+            // each shell pair writes its own block
+            for jj in 0..dj {
+                for ii in 0..di {
+                    let idx_buf = ii + di * jj;
+                    let mut val = buf_kin[idx_buf] + buf_nuc[idx_buf];
+                    if ctx_ecp.is_some() {
+                        val = val + F::from(buf_ecp[idx_buf]);
+                    }
+                    unsafe { out_mut.write((cgto_loc[idx_i] + ii) + nao * (cgto_loc[idx_j] + jj), val); }
+                }
+            }
+        });
+        return (out, vec![nao, nao]);
+    }
+}
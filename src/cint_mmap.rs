@@ -0,0 +1,258 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use memmap2::{Mmap, MmapMut};
+use crate::cint_wrapper::*;
+use crate::{AoSym, CintType, CINTR2CDATA};
+
+/* #region header */
+
+// Header of memory-mapped integral file (all numbers little endian u64, except `shl_slices` i64):
+//
+// - magic `MMAP_MAGIC` (8 bytes)
+// - version, data offset (bytes)
+// - symmetry (0: s1, 1: s2ij), order (0: f-contiguous)
+// - basis fingerprint (see `CINTR2CDATA::basis_fingerprint`)
+// - `n_center`, `shl_slices` (`n_center` pairs of i64)
+// - `n_dim`, shape
+//
+// Data (f64, little endian, the same to out-of-core integral files) starts at `MMAP_DATA_OFFSET`, which is
+// page-aligned. Data is mapped without conversion, so memory-mapped integrals are only supported on little-endian
+// targets.
+
+const MMAP_MAGIC: &[u8; 8] = b"RCINTMAP";
+const MMAP_VERSION: u64 = 1;
+const MMAP_DATA_OFFSET: u64 = 4096;
+
+/// Layout metadata of memory-mapped integral.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MmapLayout {
+    /// f-contiguous shape of integral (including component, if more than one)
+    pub shape: Vec<usize>,
+    /// symmetry of AO indices; for `S2ij` first dimension is packed lower triangle of `(i, j)`
+    pub aosym: AoSym,
+    /// shell slices of integral
+    pub shl_slices: Vec<[i32; 2]>,
+    /// fingerprint of molecule and basis the integral was evaluated for
+    pub fingerprint: u64,
+}
+
+impl MmapLayout {
+    /// Number of elements of integral.
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of bytes of integral data; `None` if it overflows.
+    fn data_bytes(&self) -> Option<u64> {
+        self.shape.iter().try_fold(8_u64, |acc, &d| acc.checked_mul(d as u64))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MMAP_MAGIC.to_vec();
+        let aosym = match self.aosym { AoSym::S1 => 0u64, AoSym::S2ij => 1 };
+        [MMAP_VERSION, MMAP_DATA_OFFSET, aosym, 0, self.fingerprint, self.shl_slices.len() as u64]
+            .iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+        self.shl_slices.iter().for_each(|&[shl_start, shl_stop]| {
+            bytes.extend((shl_start as i64).to_le_bytes());
+            bytes.extend((shl_stop as i64).to_le_bytes());
+        });
+        bytes.extend((self.shape.len() as u64).to_le_bytes());
+        self.shape.iter().for_each(|&d| bytes.extend((d as u64).to_le_bytes()));
+        if bytes.len() as u64 > MMAP_DATA_OFFSET {
+            panic!("header of memory-mapped integral exceeds {MMAP_DATA_OFFSET} bytes");
+        }
+        return bytes;
+    }
+
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if bytes.len() < 8 || &bytes[..8] != MMAP_MAGIC {
+            return Err(invalid("not a memory-mapped integral file"));
+        }
+        let mut words = bytes[8..].chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().unwrap()));
+        let mut next = || words.next().ok_or_else(|| invalid("truncated header"));
+        let version = next()?;
+        let data_offset = next()?;
+        if version != MMAP_VERSION || data_offset != MMAP_DATA_OFFSET {
+            return Err(invalid(&format!("unsupported version {version} or data offset {data_offset}")));
+        }
+        let aosym = match next()? {
+            0 => AoSym::S1,
+            1 => AoSym::S2ij,
+            v => return Err(invalid(&format!("unknown symmetry {v}"))),
+        };
+        if next()? != 0 {
+            return Err(invalid("only f-contiguous order is supported"));
+        }
+        let fingerprint = next()?;
+        let n_center = next()? as usize;
+        let mut next_shl = || i32::try_from(next()? as i64).map_err(|_| invalid("shell index out of range"));
+        let shl_slices = (0..n_center).map(|_| Ok([next_shl()?, next_shl()?])).collect::<io::Result<Vec<_>>>()?;
+        let n_dim = next()? as usize;
+        let shape = (0..n_dim).map(|_| Ok(next()? as usize)).collect::<io::Result<Vec<_>>>()?;
+        let layout = MmapLayout { shape, aosym, shl_slices, fingerprint };
+        if layout.data_bytes().is_none() {
+            return Err(invalid(&format!("size of shape {:?} overflows", layout.shape)));
+        }
+        Ok(layout)
+    }
+}
+
+/* #endregion */
+
+/// Integral tensor (f64) in memory-mapped file, with layout metadata in header.
+///
+/// Created by [`CINTR2CDATA::integral_s1_to_mmap`] (or `s2ij`), or opened read-only by [`Self::open`].
+/// Integral data could be used as an ordinary slice ([`Self::as_slice`]), while it is paged from disk by OS.
+pub struct MmapIntegral {
+    path: PathBuf,
+    layout: MmapLayout,
+    mmap: Mmap,
+}
+
+impl MmapIntegral {
+    /// Open memory-mapped integral file (read-only).
+    ///
+    /// File should not be modified by others while it is mapped.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        check_little_endian()?;
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::open(&path)?;
+        let mut header = vec![0u8; MMAP_DATA_OFFSET as usize];
+        file.read_exact(&mut header)?;
+        let layout = MmapLayout::from_bytes(&header)?;
+        // header is untrusted: data region must be exactly the rest of the file
+        let file_len = file.metadata()?.len();
+        let data_bytes = layout.data_bytes().unwrap();
+        if MMAP_DATA_OFFSET.checked_add(data_bytes) != Some(file_len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "{path:?}: data of shape {:?} does not match file length {file_len}", layout.shape)));
+        }
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(MmapIntegral { path, layout, mmap })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn layout(&self) -> &MmapLayout {
+        &self.layout
+    }
+
+    /// Whether this integral was evaluated for the same molecule and basis of `cint_data`.
+    pub fn matches(&self, cint_data: &CINTR2CDATA) -> bool {
+        self.layout.fingerprint == cint_data.basis_fingerprint()
+    }
+
+    /// Integral data, f-contiguous with shape of layout.
+    pub fn as_slice(&self) -> &[f64] {
+        // data offset is page-aligned, so that f64 data is aligned
+        let data = &self.mmap[MMAP_DATA_OFFSET as usize..];
+        unsafe { std::slice::from_raw_parts(data.as_ptr() as *const f64, self.layout.len()) }
+    }
+}
+
+/// Memory-mapped integral data is little endian, and mapped without conversion.
+fn check_little_endian() -> io::Result<()> {
+    if cfg!(target_endian = "big") {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "memory-mapped integral requires little-endian target"));
+    }
+    Ok(())
+}
+
+/// Create file with header, and map its data region (`len` f64) for writing.
+fn create_mmap(path: &Path, layout: &MmapLayout) -> io::Result<MmapMut> {
+    check_little_endian()?;
+    let file_len = layout.data_bytes().and_then(|bytes| bytes.checked_add(MMAP_DATA_OFFSET)).ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidInput, format!("size of shape {:?} overflows", layout.shape)))?;
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+    file.write_all(&layout.to_bytes())?;
+    file.set_len(file_len)?;
+    let mmap = unsafe { MmapMut::map_mut(&file)? };
+    Ok(mmap)
+}
+
+/// Data region of mapped file as mutable f64 slice.
+fn mmap_data_mut(mmap: &mut MmapMut, len: usize) -> &mut [f64] {
+    let data = &mut mmap[MMAP_DATA_OFFSET as usize..];
+    unsafe { std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut f64, len) }
+}
+
+impl CINTR2CDATA {
+    /// Fingerprint (FNV-1a hash) of molecule and basis: GTO type, `c_atm`, `c_bas` and `c_env`.
+    ///
+    /// Used to check whether integrals stored on disk belong to this molecule and basis.
+    pub fn basis_fingerprint(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |bytes: &[u8]| bytes.iter().for_each(|&b| {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        });
+        let cint_type: u8 = match self.cint_type { CintType::Spheric => 0, CintType::Cartesian => 1, CintType::Spinor => 2 };
+        feed(&[cint_type]);
+        self.c_atm.iter().for_each(|v| feed(&v.to_le_bytes()));
+        self.c_bas.iter().for_each(|v| feed(&v.to_le_bytes()));
+        self.c_env.iter().for_each(|v| feed(&v.to_bits().to_le_bytes()));
+        return hash;
+    }
+
+    /// Evaluate integral (s1 symmetry) directly into a memory-mapped file.
+    ///
+    /// Output is not allocated in memory; pages of file are written by integral engine and flushed to disk.
+    /// Layout metadata (shape, order, symmetry, shell slices and basis fingerprint) is stored in file header.
+    pub fn integral_s1_to_mmap<T> (&self, path: impl AsRef<Path>, shl_slices: Option<&[[i32; 2]]>) -> io::Result<MmapIntegral>
+    where
+        T: Integrator
+    {
+        self.integral_to_mmap::<T>(path, AoSym::S1, shl_slices)
+    }
+
+    /// Evaluate integral (s2ij symmetry) directly into a memory-mapped file.
+    ///
+    /// See also [`Self::integral_s1_to_mmap`].
+    pub fn integral_s2ij_to_mmap<T> (&self, path: impl AsRef<Path>, shl_slices: Option<&[[i32; 2]]>) -> io::Result<MmapIntegral>
+    where
+        T: Integrator
+    {
+        self.integral_to_mmap::<T>(path, AoSym::S2ij, shl_slices)
+    }
+
+    fn integral_to_mmap<T> (&self, path: impl AsRef<Path>, aosym: AoSym, shl_slices: Option<&[[i32; 2]]>) -> io::Result<MmapIntegral>
+    where
+        T: Integrator
+    {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by memory-mapped integral evaluation");
+        }
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices.to_vec(),
+            None => vec![[0, self.c_nbas]; T::n_center()],
+        };
+        let ctx = self.eval_context::<T, f64>();
+        let mut shape = match aosym {
+            AoSym::S1 => ctx.cgto_shape::<T>(&shl_slices),
+            AoSym::S2ij => ctx.cgto_shape_s2ij::<T>(&shl_slices).unwrap(),
+        };
+        if T::n_comp() > 1 { shape.push(T::n_comp()); }
+        let layout = MmapLayout { shape, aosym, shl_slices: shl_slices.clone(), fingerprint: self.basis_fingerprint() };
+
+        let path = path.as_ref();
+        let mut mmap = create_mmap(path, &layout)?;
+        {
+            let out = mmap_data_mut(&mut mmap, layout.len());
+            match aosym {
+                AoSym::S1 => ctx.integral_s1_inplace::<f64>(out, &shl_slices),
+                AoSym::S2ij => ctx.integral_s2ij_inplace::<f64>(out, &shl_slices),
+            }
+        }
+        mmap.flush()?;
+        drop(mmap);
+        MmapIntegral::open(path)
+    }
+}
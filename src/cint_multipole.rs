@@ -0,0 +1,169 @@
+use itertools::Itertools;
+use crate::cint_wrapper::*;
+use crate::CINTR2CDATA;
+
+/// Maximum order of multipole integrals, limited by integrators available in libcint
+/// (`int1e_ovlp`, `int1e_r`, `int1e_rr`, `int1e_rrr`, `int1e_rrrr`).
+pub const MULTIPOLE_MAX_ORDER: usize = 4;
+
+/// Origin of multipole operators.
+#[derive(Clone, Debug)]
+pub enum MultipoleOrigin {
+    /// User-defined coordinate (in Bohr).
+    Coord([f64; 3]),
+    /// Center of nuclear charges (by `atom_charges`).
+    ChargeCenter,
+    /// Center of mass, with atomic masses given by caller (`c_atm` does not contain masses).
+    MassCenter(Vec<f64>),
+}
+
+/// Unique cartesian components `[a, b, c]` (for `x^a y^b z^c`) of multipole of certain order.
+///
+/// Ordering is the same to PySCF's cartesian GTO convention, e.g. `xx, xy, xz, yy, yz, zz` for order 2.
+pub fn multipole_cart_components(order: usize) -> Vec<[usize; 3]> {
+    (0..=order).rev().flat_map(|a| {
+        (0..=(order - a)).rev().map(move |b| [a, b, order - a - b])
+    }).collect()
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    if k > n { return 0.; }
+    (0..k).fold(1., |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+fn factorial(n: usize) -> f64 {
+    (1..=n).fold(1., |acc, i| acc * i as f64)
+}
+
+/// Coefficients of real regular solid harmonics (Racah normalization) in unique cartesian components.
+///
+/// Output has `2l + 1` rows (ordered by `m = -l, ..., l`), each row is coefficients of
+/// [`multipole_cart_components`]. For example, `m = 0` of order 2 is `zz - (xx + yy) / 2`.
+/// Formula follows Helgaker, Jorgensen and Olsen, Molecular Electronic-Structure Theory, eq (6.4.47).
+pub fn multipole_sph_coefficients(l: usize) -> Vec<Vec<f64>> {
+    let carts = multipole_cart_components(l);
+    let index = |cart: [usize; 3]| carts.iter().position(|&x| x == cart).unwrap();
+    (-(l as i32)..=(l as i32)).map(|m| {
+        let ma = m.unsigned_abs() as usize;
+        let mut coef = vec![0.; carts.len()];
+        let norm_m0 = if m == 0 { 2. } else { 1. };
+        let norm = (2. * factorial(l + ma) * factorial(l - ma) / norm_m0).sqrt() / (2_f64.powi(ma as i32) * factorial(l));
+        // k = 2v is even for m >= 0, and odd for m < 0
+        let k_start = if m >= 0 { 0 } else { 1 };
+        for t in 0..=((l - ma) / 2) {
+            for u in 0..=t {
+                for k in (k_start..=ma).step_by(2) {
+                    let sign = if (t + (k - k_start) / 2) % 2 == 0 { 1. } else { -1. };
+                    let c = sign * 0.25_f64.powi(t as i32)
+                        * binomial(l, t) * binomial(l - t, ma + t) * binomial(t, u) * binomial(ma, k);
+                    coef[index([2 * t + ma - 2 * u - k, 2 * u + k, l - 2 * t - ma])] += norm * c;
+                }
+            }
+        }
+        coef
+    }).collect()
+}
+
+impl CINTR2CDATA {
+
+    /// Coordinate of multipole origin.
+    pub fn multipole_origin(&self, origin: &MultipoleOrigin) -> [f64; 3] {
+        let coords = self.atom_coords();
+        let weighted_center = |weights: &[f64]| {
+            let total = weights.iter().sum::<f64>();
+            let mut center = [0.; 3];
+            coords.iter().zip(weights).for_each(|(r, w)| (0..3).for_each(|t| center[t] += w * r[t] / total));
+            center
+        };
+        match origin {
+            MultipoleOrigin::Coord(coord) => *coord,
+            MultipoleOrigin::ChargeCenter => weighted_center(&self.atom_charges()),
+            MultipoleOrigin::MassCenter(masses) => {
+                if masses.len() != coords.len() {
+                    panic!("number of masses {} is not the same to number of atoms {}", masses.len(), coords.len());
+                }
+                weighted_center(masses)
+            },
+        }
+    }
+
+    /// Unique cartesian multipole integrals `<i|(x-Ox)^a (y-Oy)^b (z-Oz)^c|j>` with `a + b + c = order`.
+    ///
+    /// Output tensor is f-contiguous with shape `(i, j, comp)`, where components are ordered as
+    /// [`multipole_cart_components`]. Common origin of `self` is not modified.
+    ///
+    /// Order is limited to [`MULTIPOLE_MAX_ORDER`] (4) by integrators available in libcint; panics for higher order.
+    pub fn integral_multipole_cart(&self, order: usize, origin: &MultipoleOrigin) -> (Vec<f64>, Vec<usize>) {
+        let orig = self.multipole_origin(origin);
+        let (out_full, out_shape_full) = self.with_common_origin(&orig, |data| match order {
+            0 => data.integral_s1::<int1e_ovlp>(None),
+            1 => data.integral_s1::<int1e_r>(None),
+            2 => data.integral_s1::<int1e_rr>(None),
+            3 => data.integral_s1::<int1e_rrr>(None),
+            4 => data.integral_s1::<int1e_rrrr>(None),
+            _ => panic!("multipole integral of order {order} is not available in libcint (maximum order {MULTIPOLE_MAX_ORDER})"),
+        });
+        let size_ij = out_shape_full[0] * out_shape_full[1];
+        let carts = multipole_cart_components(order);
+        // component of libcint tensor r_{t1} r_{t2} ... is `t1 * 3^(n-1) + t2 * 3^(n-2) + ...`;
+        // tensor is symmetric, so any permutation of the tuple refers to the same component
+        let out = carts.iter().flat_map(|&[a, b, c]| {
+            let comp = [vec![0; a], vec![1; b], vec![2; c]].concat().iter().fold(0, |acc, t| acc * 3 + t);
+            out_full[comp * size_ij..(comp + 1) * size_ij].iter().copied()
+        }).collect_vec();
+        let out_shape = vec![out_shape_full[0], out_shape_full[1], carts.len()];
+        return (out, out_shape);
+    }
+
+    /// Spherical (traceless) multipole integrals, by real regular solid harmonics of Racah normalization.
+    ///
+    /// Output tensor is f-contiguous with shape `(i, j, m)`, where `m = -l, ..., l`.
+    /// Order is limited to [`MULTIPOLE_MAX_ORDER`] (4). See also [`multipole_sph_coefficients`] and
+    /// [`Self::integral_multipole_cart`].
+    pub fn integral_multipole_sph(&self, l: usize, origin: &MultipoleOrigin) -> (Vec<f64>, Vec<usize>) {
+        let (out_cart, out_shape_cart) = self.integral_multipole_cart(l, origin);
+        let size_ij = out_shape_cart[0] * out_shape_cart[1];
+        let coefs = multipole_sph_coefficients(l);
+        let mut out = vec![0.; size_ij * coefs.len()];
+        out.chunks_mut(size_ij).zip(&coefs).for_each(|(out_m, coef)| {
+            coef.iter().enumerate().filter(|(_, &c)| c != 0.).for_each(|(n, &c)| {
+                out_m.iter_mut().zip(&out_cart[n * size_ij..(n + 1) * size_ij]).for_each(|(o, v)| *o += c * v);
+            });
+        });
+        let out_shape = vec![out_shape_cart[0], out_shape_cart[1], coefs.len()];
+        return (out, out_shape);
+    }
+
+    /// Unique cartesian multipole moments of molecule, for given AO density matrix.
+    ///
+    /// Nuclear contribution is positive and electronic contribution is negative, e.g. dipole moment is
+    /// `sum_A Z_A (R_A - O) - sum_{ij} D_ij <i|r - O|j>`. Density matrix is f-contiguous with shape (nao, nao).
+    /// Order is limited to [`MULTIPOLE_MAX_ORDER`] (4).
+    pub fn multipole_cart_expectation(&self, order: usize, origin: &MultipoleOrigin, dm: &[f64]) -> Vec<f64> {
+        let orig = self.multipole_origin(origin);
+        let (out, out_shape) = self.integral_multipole_cart(order, origin);
+        let size_ij = out_shape[0] * out_shape[1];
+        if dm.len() != size_ij {
+            panic!("size of density matrix {} is not the same to nao * nao = {}", dm.len(), size_ij);
+        }
+        let coords = self.atom_coords();
+        let charges = self.atom_charges();
+        multipole_cart_components(order).iter().enumerate().map(|(n, &[a, b, c])| {
+            let nuc = coords.iter().zip(&charges).map(|(r, z)| {
+                z * (r[0] - orig[0]).powi(a as i32) * (r[1] - orig[1]).powi(b as i32) * (r[2] - orig[2]).powi(c as i32)
+            }).sum::<f64>();
+            let elec = out[n * size_ij..(n + 1) * size_ij].iter().zip(dm).map(|(v, d)| v * d).sum::<f64>();
+            nuc - elec
+        }).collect()
+    }
+
+    /// Spherical (traceless) multipole moments of molecule, for given AO density matrix.
+    ///
+    /// Components are ordered by `m = -l, ..., l`. See also [`Self::multipole_cart_expectation`].
+    pub fn multipole_sph_expectation(&self, l: usize, origin: &MultipoleOrigin, dm: &[f64]) -> Vec<f64> {
+        let cart = self.multipole_cart_expectation(l, origin, dm);
+        multipole_sph_coefficients(l).iter().map(|coef| {
+            coef.iter().zip(&cart).map(|(c, v)| c * v).sum::<f64>()
+        }).collect()
+    }
+}
@@ -0,0 +1,78 @@
+use crate::CINTR2CDATA;
+
+impl CINTR2CDATA {
+    /// Pairs of atoms `(a, b)` with `b < a` and non-zero charge product, with charge product and `R_a - R_b`.
+    ///
+    /// Pairs involving ghost atoms (zero charge) are skipped, so that ghost atoms placed on real atoms (such as
+    /// counterpoise correction) do not give singular distances.
+    fn nuclear_pairs(&self) -> Vec<(usize, usize, f64, [f64; 3])> {
+        let coords = self.atom_coords();
+        let charges = self.atom_charges();
+        let mut pairs = vec![];
+        for a in 0..coords.len() {
+            for b in 0..a {
+                let zz = charges[a] * charges[b];
+                if zz == 0. { continue; }
+                let d = [0, 1, 2].map(|t| coords[a][t] - coords[b][t]);
+                pairs.push((a, b, zz, d));
+            }
+        }
+        return pairs;
+    }
+
+    /// Nuclear repulsion energy `sum_{A<B} Z_A Z_B / R_AB`.
+    ///
+    /// Charges are [`Self::atom_charges`]: fractional charges (`PTR_FRAC_CHARGE`) are used if set, and ECP atoms
+    /// take their effective charges stored in `c_atm` (nuclear charge minus ECP core electrons, the same charges
+    /// used by `int1e_nuc`). Ghost atoms (zero charge) do not contribute.
+    pub fn nuclear_repulsion_energy(&self) -> f64 {
+        self.nuclear_pairs().iter().map(|(_, _, zz, d)| {
+            zz / (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt()
+        }).sum()
+    }
+
+    /// Cartesian gradient of nuclear repulsion energy.
+    ///
+    /// Output is f-contiguous with shape `(3, natm)`, the same layout as [`Self::atom_coords`].
+    /// See also [`Self::nuclear_repulsion_energy`] for charges.
+    pub fn nuclear_repulsion_gradient(&self) -> (Vec<f64>, Vec<usize>) {
+        let natm = self.c_natm as usize;
+        let mut out = vec![0.; 3 * natm];
+        for (a, b, zz, d) in self.nuclear_pairs() {
+            let r = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+            let fac = zz / (r * r * r);
+            for t in 0..3 {
+                out[t + 3 * a] -= fac * d[t];
+                out[t + 3 * b] += fac * d[t];
+            }
+        }
+        return (out, vec![3, natm]);
+    }
+
+    /// Cartesian Hessian of nuclear repulsion energy.
+    ///
+    /// Output is f-contiguous with shape `(3, natm, 3, natm)`; element `(t, A, u, B)` is
+    /// `d^2 E / dR_{A,t} dR_{B,u}`, so that it could also be regarded as `(3 natm, 3 natm)` matrix.
+    /// See also [`Self::nuclear_repulsion_energy`] for charges.
+    pub fn nuclear_repulsion_hessian(&self) -> (Vec<f64>, Vec<usize>) {
+        let natm = self.c_natm as usize;
+        let n = 3 * natm;
+        let mut out = vec![0.; n * n];
+        for (a, b, zz, d) in self.nuclear_pairs() {
+            let r2 = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+            let r = r2.sqrt();
+            for t in 0..3 {
+                for u in 0..3 {
+                    let delta = if t == u { 1. } else { 0. };
+                    // d^2 E / dR_{a,t} dR_{b,u}; diagonal blocks follow from translational invariance
+                    let h = zz * (delta - 3. * d[t] * d[u] / r2) / (r2 * r);
+                    out[(t + 3 * a) + n * (u + 3 * b)] += h;
+                    out[(t + 3 * b) + n * (u + 3 * a)] += h;
+                    out[(t + 3 * a) + n * (u + 3 * a)] -= h;
+                    out[(t + 3 * b) + n * (u + 3 * b)] -= h;
+                }
+            }
+        }
+        return (out, vec![3, natm, 3, natm]);
+    }
+}
@@ -0,0 +1,334 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use itertools::Itertools;
+use crate::cint_wrapper::*;
+use crate::cint_context::CINTEvalContext;
+use crate::cint_parallel::ThreadBuffers;
+use crate::cint_schedule::schedule_by_cost;
+use crate::{CintType, CINTR2CDATA};
+
+/* #region file format */
+
+// Simple binary format (all numbers little endian):
+//
+// - magic `OOC_MAGIC` (8 bytes)
+// - `n_center`, `n_comp`, `n_block` (u64)
+// - `shl_slices` of whole integral: `n_center` pairs of (start, stop) (i64)
+// - block index, for each block:
+//     - outer shells of block (i64, `n_outer_of(n_center)` values)
+//     - offset of block data in file (u64, in bytes)
+//     - shape of block (u64, `n_center` values, and one more `n_comp` if `n_comp > 1`)
+// - block data, each block f-contiguous (f64)
+
+const OOC_MAGIC: &[u8; 8] = b"RCINTOOC";
+
+/// Number of outer (blocked) axes: one shell per block on these axes, all shells of inner axes.
+///
+/// Blocks are `(i, j_shell)` for 2-center, `(i, j, k_shell)` for 3-center, `(i, j, k_shell, l_shell)` for 4-center.
+fn n_outer_of(n_center: usize) -> usize {
+    match n_center {
+        2 | 3 => 1,
+        4 => 2,
+        _ => panic!("Not known centers {n_center:}"),
+    }
+}
+
+fn write_u64(w: &mut impl Write, v: u64) -> io::Result<()> { w.write_all(&v.to_le_bytes()) }
+fn write_i64(w: &mut impl Write, v: i64) -> io::Result<()> { w.write_all(&v.to_le_bytes()) }
+
+/// Write all of `bytes` at `offset` of file, without moving a shared cursor (so threads need no lock).
+#[cfg(unix)]
+fn write_all_at(file: &File, bytes: &[u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(bytes, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut bytes: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !bytes.is_empty() {
+        match file.seek_write(bytes, offset) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole block")),
+            Ok(n) => { bytes = &bytes[n..]; offset += n as u64; },
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_i64(r: &mut impl Read) -> io::Result<i64> {
+    let mut bytes = [0u8; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(i64::from_le_bytes(bytes))
+}
+
+/* #endregion */
+
+/// Index entry of one block stored on disk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OOCBlock {
+    /// shells of outer axes of this block
+    pub outer_shls: Vec<i32>,
+    /// shell slices of this block (full slices on inner axes, one shell on outer axes)
+    pub shl_slices: Vec<[i32; 2]>,
+    /// f-contiguous shape of this block (including component, if more than one)
+    pub shape: Vec<usize>,
+    /// offset of block data in file, in bytes
+    pub offset: u64,
+}
+
+impl OOCBlock {
+    /// Number of elements of this block.
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Integral (s1 symmetry, f64) stored on disk by blocks of outer shells.
+///
+/// Created by [`CINTR2CDATA::integral_s1_to_file`], or opened from an existing file by [`Self::open`].
+pub struct OutOfCoreIntegral {
+    path: PathBuf,
+    file: Mutex<File>,
+    n_comp: usize,
+    shl_slices: Vec<[i32; 2]>,
+    blocks: Vec<OOCBlock>,
+}
+
+impl OutOfCoreIntegral {
+    /// Open integral file written by [`CINTR2CDATA::integral_s1_to_file`].
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+        let file_len = file.metadata()?.len();
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{path:?}: {msg}"));
+        let mut reader = BufReader::new(file);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != OOC_MAGIC {
+            return Err(invalid("not an integral file".to_string()));
+        }
+        let n_center = read_u64(&mut reader)? as usize;
+        let n_comp = read_u64(&mut reader)? as usize;
+        let n_block = read_u64(&mut reader)? as usize;
+        if !(2..=4).contains(&n_center) {
+            return Err(invalid(format!("invalid number of centers {n_center}")));
+        }
+        let read_shl = |reader: &mut BufReader<File>| i32::try_from(read_i64(reader)?)
+            .map_err(|_| invalid("shell index out of range".to_string()));
+        let shl_slices = (0..n_center).map(|_| Ok([read_shl(&mut reader)?, read_shl(&mut reader)?]))
+            .collect::<io::Result<Vec<[i32; 2]>>>()?;
+        let n_outer = n_outer_of(n_center);
+        let n_dim = n_center + if n_comp > 1 { 1 } else { 0 };
+
+        // header is untrusted: block index and block data must lie within the file
+        let header_size = (n_block as u64).checked_mul(8 * (n_outer + 1 + n_dim) as u64)
+            .and_then(|size| size.checked_add(8 * (4 + 2 * n_center) as u64))
+            .filter(|&size| size <= file_len)
+            .ok_or_else(|| invalid(format!("block index of {n_block} blocks exceeds file length {file_len}")))?;
+        let mut blocks = Vec::with_capacity(n_block);
+        for idx_block in 0..n_block {
+            let outer_shls = (0..n_outer).map(|_| read_shl(&mut reader)).collect::<io::Result<Vec<i32>>>()?;
+            let offset = read_u64(&mut reader)?;
+            let shape = (0..n_dim).map(|_| Ok(read_u64(&mut reader)? as usize)).collect::<io::Result<Vec<usize>>>()?;
+            let end = shape.iter().try_fold(8_u64, |acc, &d| acc.checked_mul(d as u64))
+                .and_then(|size| size.checked_add(offset))
+                .filter(|&end| offset >= header_size && end <= file_len);
+            if end.is_none() {
+                return Err(invalid(format!("block {idx_block} (offset {offset}, shape {shape:?}) exceeds file length {file_len}")));
+            }
+            let mut block_slices = shl_slices.clone();
+            outer_shls.iter().enumerate().for_each(|(n, &shl)| block_slices[n_center - n_outer + n] = [shl, shl + 1]);
+            blocks.push(OOCBlock { outer_shls, shl_slices: block_slices, shape, offset });
+        }
+        let file = Mutex::new(reader.into_inner());
+        Ok(OutOfCoreIntegral { path, file, n_comp, shl_slices, blocks })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Shell slices of the whole integral.
+    pub fn shl_slices(&self) -> &[[i32; 2]] {
+        &self.shl_slices
+    }
+
+    pub fn n_comp(&self) -> usize {
+        self.n_comp
+    }
+
+    /// Index of all blocks, ordered by outer shells (f-order).
+    pub fn blocks(&self) -> &[OOCBlock] {
+        &self.blocks
+    }
+
+    /// Index of block with given outer shells (absolute shell indices).
+    pub fn find_block(&self, outer_shls: &[i32]) -> Option<usize> {
+        self.blocks.iter().position(|block| block.outer_shls == outer_shls)
+    }
+
+    /// Read back one block, returning f-contiguous block data and its shape.
+    pub fn read_block(&self, idx_block: usize) -> io::Result<(Vec<f64>, Vec<usize>)> {
+        let block = self.blocks.get(idx_block).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput, format!("block index {idx_block} exceeds number of blocks {}", self.blocks.len())))?;
+        let mut bytes = vec![0u8; block.len() * 8];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(block.offset))?;
+            file.read_exact(&mut bytes)?;
+        }
+        let out = bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect();
+        Ok((out, block.shape.clone()))
+    }
+}
+
+impl CINTR2CDATA {
+    /// Evaluate integral (s1 symmetry) and stream it to file by blocks, for integrals exceeding memory.
+    ///
+    /// Each block contains all shells of inner axes and one shell of outer axes: `(i, j_shell)` for 2-center,
+    /// `(i, j, k_shell)` for 3-center (such as auxiliary shells of `int3c2e`), `(i, j, k_shell, l_shell)` for
+    /// 4-center integrals. Blocks are evaluated in parallel, with one block per thread kept in memory, and
+    /// written to file as soon as they are computed. See [`OutOfCoreIntegral`] for reading back blocks.
+    pub fn integral_s1_to_file<T> (&self, path: impl AsRef<Path>, shl_slices: Option<&[[i32; 2]]>) -> io::Result<OutOfCoreIntegral>
+    where
+        T: Integrator
+    {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by out-of-core integral evaluation");
+        }
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices.to_vec(),
+            None => vec![[0, self.c_nbas]; T::n_center()],
+        };
+        self.check_shl_slices::<T>(&shl_slices).unwrap();
+        let ctx = self.eval_context::<T, f64>();
+
+        let n_center = T::n_center();
+        let n_comp = T::n_comp();
+        let n_outer = n_outer_of(n_center);
+        let n_inner = n_center - n_outer;
+
+        /* #region 1. block index */
+
+        // outer shells in f-order
+        let outer_shls_list = match n_outer {
+            1 => (shl_slices[n_inner][0]..shl_slices[n_inner][1]).map(|shl| vec![shl]).collect_vec(),
+            _ => (shl_slices[n_inner + 1][0]..shl_slices[n_inner + 1][1])
+                .cartesian_product(shl_slices[n_inner][0]..shl_slices[n_inner][1])
+                .map(|(shl_l, shl_k)| vec![shl_k, shl_l]).collect_vec(),
+        };
+        let n_dim = n_center + if n_comp > 1 { 1 } else { 0 };
+        let header_size = 8 * (4 + 2 * n_center + outer_shls_list.len() * (n_outer + 1 + n_dim));
+        let mut offset = header_size as u64;
+        let blocks = outer_shls_list.into_iter().map(|outer_shls| {
+            let mut block_slices = shl_slices.clone();
+            outer_shls.iter().enumerate().for_each(|(n, &shl)| block_slices[n_inner + n] = [shl, shl + 1]);
+            let mut shape = ctx.cgto_shape::<T>(&block_slices);
+            if n_comp > 1 { shape.push(n_comp); }
+            let block = OOCBlock { outer_shls, shl_slices: block_slices, shape, offset };
+            offset += block.len() as u64 * 8;
+            block
+        }).collect_vec();
+
+        /* #endregion */
+
+        /* #region 2. header */
+
+        let path = path.as_ref().to_path_buf();
+        {
+            let mut writer = BufWriter::new(File::create(&path)?);
+            writer.write_all(OOC_MAGIC)?;
+            write_u64(&mut writer, n_center as u64)?;
+            write_u64(&mut writer, n_comp as u64)?;
+            write_u64(&mut writer, blocks.len() as u64)?;
+            for &[shl_start, shl_stop] in &shl_slices {
+                write_i64(&mut writer, shl_start as i64)?;
+                write_i64(&mut writer, shl_stop as i64)?;
+            }
+            for block in &blocks {
+                for &shl in &block.outer_shls { write_i64(&mut writer, shl as i64)?; }
+                write_u64(&mut writer, block.offset)?;
+                for &d in &block.shape { write_u64(&mut writer, d as u64)?; }
+            }
+            writer.flush()?;
+        }
+
+        /* #endregion */
+
+        /* #region 3. parallel block evaluation and writing */
+
+        // blocks are written by positioned writes at their own offsets, so threads never wait for each other on file
+        let file = File::options().write(true).open(&path)?;
+        let io_error = Mutex::new(None);
+
+        let cache_size = ctx.size_of_cache::<T>(&shl_slices);
+        let block_size = blocks.iter().map(|block| block.len()).max().unwrap_or(0);
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
+        let thread_block = ThreadBuffers::new(&self.parallelism, block_size, 0.);
+        let thread_bytes = ThreadBuffers::new(&self.parallelism, block_size * 8, 0u8);
+
+        // expensive blocks are dispatched first
+        let task_costs = blocks.iter().map(|block| ctx.shell_tuple_cost(&block.outer_shls)).collect_vec();
+        let task_order = schedule_by_cost(&task_costs);
+
+        self.parallelism.for_each_ordered(&task_order, |idx_block| {
+            let block = &blocks[idx_block];
+            let mut cache = thread_cache.get();
+            let mut buf = thread_block.get();
+            let buf = &mut buf[..block.len()];
+            block_integral_s1(&ctx, buf, &block.shl_slices, &mut cache);
+
+            let mut bytes = thread_bytes.get();
+            let bytes = &mut bytes[..block.len() * 8];
+            bytes.chunks_exact_mut(8).zip(buf.iter()).for_each(|(b, v)| b.copy_from_slice(&v.to_le_bytes()));
+            if let Err(err) = write_all_at(&file, bytes, block.offset) {
+                io_error.lock().unwrap().get_or_insert(err);
+            }
+        });
+
+        if let Some(err) = io_error.into_inner().unwrap() {
+            return Err(err);
+        }
+        file.sync_all()?;
+
+        /* #endregion */
+
+        let file = Mutex::new(File::open(&path)?);
+        Ok(OutOfCoreIntegral { path, file, n_comp, shl_slices, blocks })
+    }
+}
+
+/// Evaluate one block (s1 symmetry) sequentially into `out`, f-contiguous with shape of `shl_slices`.
+fn block_integral_s1<T> (ctx: &CINTEvalContext<'_, T>, out: &mut [f64], shl_slices: &[[i32; 2]], cache: &mut [f64])
+where
+    T: Integrator
+{
+    let cgto_shape = ctx.cgto_shape::<T>(shl_slices);
+    let cgto_shape_i32 = cgto_shape.iter().map(|&v| v as i32).collect_vec();
+    let cgto_locs_rel = ctx.cgto_loc_slices_relative(shl_slices);
+    shl_slices.iter().enumerate()
+        .map(|(n, &[shl_start, shl_stop])| (shl_start..shl_stop).map(move |shl| (n, shl)))
+        .multi_cartesian_product()
+        .for_each(|shls_with_axis| {
+            let shls = shls_with_axis.iter().map(|&(_, shl)| shl).collect_vec();
+            let offset = shls_with_axis.iter().rev().fold(0, |acc, &(n, shl)| {
+                acc * cgto_shape[n] + cgto_locs_rel[n][(shl - shl_slices[n][0]) as usize]
+            });
+            // shell block with strides of `cgto_shape` lies within `out[offset..]`
+            unsafe { ctx.integral_block::<f64>(&mut out[offset..], &shls, &cgto_shape_i32, cache); }
+        });
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ptr::null_mut;
+use std::sync::{Arc, Mutex};
+use crate::cint_wrapper::*;
+use crate::cecp_wrapper::*;
+use crate::cint::{self, CINTOpt};
+use crate::cecp::{self, ECPOpt};
+
+/* #region owned optimizers */
+
+/// Owned libcint optimizer (`CINTOpt`) of one integrator; freed on drop.
+///
+/// libcint does not provide copy of optimizer, so this type is not `Clone`; share it by `Arc` instead.
+pub struct CINTOptimizer {
+    c_opt: *mut CINTOpt,
+}
+
+// Optimizer is never modified after creation; libcint only reads it during integral evaluation.
+unsafe impl Send for CINTOptimizer {}
+unsafe impl Sync for CINTOptimizer {}
+
+impl CINTOptimizer {
+    /// Create optimizer of integrator `T`, for given `c_atm`, `c_bas`, `c_env`.
+    pub fn new<T> (c_atm: &[i32], c_natm: i32, c_bas: &[i32], c_nbas: i32, c_env: &[f64]) -> Self
+    where
+        T: Integrator
+    {
+        let mut c_opt = null_mut();
+        unsafe { T::optimizer(&mut c_opt, c_atm.as_ptr(), c_natm, c_bas.as_ptr(), c_nbas, c_env.as_ptr()); }
+        CINTOptimizer { c_opt }
+    }
+
+    /// Empty optimizer (null pointer); integrals evaluated without optimizer are still correct, but slower.
+    pub fn null() -> Self {
+        CINTOptimizer { c_opt: null_mut() }
+    }
+
+    /// Take ownership of optimizer generated by C function.
+    ///
+    /// # Safety
+    ///
+    /// Pointer should be either null or a valid optimizer not owned by others; it will be freed on drop.
+    pub unsafe fn from_raw(c_opt: *mut CINTOpt) -> Self {
+        CINTOptimizer { c_opt }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.c_opt.is_null()
+    }
+
+    pub fn as_ptr(&self) -> *const CINTOpt {
+        self.c_opt
+    }
+}
+
+impl Default for CINTOptimizer {
+    fn default() -> Self {
+        CINTOptimizer::null()
+    }
+}
+
+impl Drop for CINTOptimizer {
+    fn drop(&mut self) {
+        unsafe { cint::CINTdel_optimizer(&mut self.c_opt); }
+    }
+}
+
+/// Owned ECP optimizer (`ECPOpt`) of one integrator; freed on drop.
+pub struct ECPOptimizer {
+    c_opt: *mut ECPOpt,
+}
+
+// Optimizer is never modified after creation; it is only read during integral evaluation.
+unsafe impl Send for ECPOptimizer {}
+unsafe impl Sync for ECPOptimizer {}
+
+impl ECPOptimizer {
+    /// Create optimizer of ECP integrator `T`, for given `c_atm`, `c_bas` (including ECP shells), `c_env`.
+    pub fn new<T> (c_atm: &[i32], c_natm: i32, c_bas: &[i32], c_nbas: i32, c_env: &[f64]) -> Self
+    where
+        T: ECPIntegrator
+    {
+        let mut c_opt = null_mut();
+        unsafe { T::optimizer(&mut c_opt, c_atm.as_ptr(), c_natm, c_bas.as_ptr(), c_nbas, c_env.as_ptr()); }
+        ECPOptimizer { c_opt }
+    }
+
+    /// Empty optimizer (null pointer); integrals evaluated without optimizer are still correct, but slower.
+    pub fn null() -> Self {
+        ECPOptimizer { c_opt: null_mut() }
+    }
+
+    /// Take ownership of optimizer generated by C function.
+    ///
+    /// # Safety
+    ///
+    /// Pointer should be either null or a valid optimizer not owned by others; it will be freed on drop.
+    pub unsafe fn from_raw(c_opt: *mut ECPOpt) -> Self {
+        ECPOptimizer { c_opt }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.c_opt.is_null()
+    }
+
+    pub fn as_ptr(&self) -> *const ECPOpt {
+        self.c_opt
+    }
+}
+
+impl Default for ECPOptimizer {
+    fn default() -> Self {
+        ECPOptimizer::null()
+    }
+}
+
+impl Drop for ECPOptimizer {
+    fn drop(&mut self) {
+        unsafe { cecp::ECPdel_optimizer(&mut self.c_opt); }
+    }
+}
+
+/* #endregion */
+
+/* #region optimizer cache */
+
+/// Hash and lengths of molecule data (`c_atm`, `c_bas`, `c_env`) that an optimizer is generated for.
+///
+/// Digest is computed without holding the cache lock. Cached optimizer is reused only if digest matches;
+/// molecule data itself is not stored nor compared (`c_env` is hashed bitwise, 64-bit hash plus lengths).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MoleculeDigest {
+    hash: u64,
+    n_atm: usize,
+    n_bas: usize,
+    n_env: usize,
+}
+
+impl MoleculeDigest {
+    pub fn new(c_atm: &[i32], c_bas: &[i32], c_env: &[f64]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        c_atm.hash(&mut hasher);
+        c_bas.hash(&mut hasher);
+        c_env.iter().for_each(|v| v.to_bits().hash(&mut hasher));
+        MoleculeDigest { hash: hasher.finish(), n_atm: c_atm.len(), n_bas: c_bas.len(), n_env: c_env.len() }
+    }
+}
+
+/// Cache of optimizers keyed by integrator name.
+///
+/// Each integrator keeps at most one optimizer, together with digest of molecule data it was generated for.
+/// When geometry, basis or `c_env` is changed, the cached optimizer no longer matches and is regenerated.
+#[derive(Default)]
+pub struct OptimizerCache {
+    cint: HashMap<&'static str, (MoleculeDigest, Arc<CINTOptimizer>)>,
+    ecp: HashMap<&'static str, (MoleculeDigest, Arc<ECPOptimizer>)>,
+}
+
+impl OptimizerCache {
+
+    /// Cached optimizer of integrator `T`; generated if not found or molecule data changed.
+    pub fn get_cint<T> (&mut self, c_atm: &[i32], c_natm: i32, c_bas: &[i32], c_nbas: i32, c_env: &[f64]) -> Arc<CINTOptimizer>
+    where
+        T: Integrator
+    {
+        let digest = MoleculeDigest::new(c_atm, c_bas, c_env);
+        self.get_cint_with_digest::<T>(&digest, c_atm, c_natm, c_bas, c_nbas, c_env)
+    }
+
+    /// Same to [`Self::get_cint`], with digest of molecule data computed by caller (before locking cache).
+    pub(crate) fn get_cint_with_digest<T> (
+        &mut self, digest: &MoleculeDigest,
+        c_atm: &[i32], c_natm: i32, c_bas: &[i32], c_nbas: i32, c_env: &[f64]) -> Arc<CINTOptimizer>
+    where
+        T: Integrator
+    {
+        if let Some((key, opt)) = self.cint.get(T::name()) {
+            if key == digest {
+                return opt.clone();
+            }
+        }
+        let opt = Arc::new(CINTOptimizer::new::<T>(c_atm, c_natm, c_bas, c_nbas, c_env));
+        self.cint.insert(T::name(), (*digest, opt.clone()));
+        return opt;
+    }
+
+    /// Cached optimizer of ECP integrator `T`; generated if not found or molecule data changed.
+    pub fn get_ecp<T> (&mut self, c_atm: &[i32], c_natm: i32, c_bas: &[i32], c_nbas: i32, c_env: &[f64]) -> Arc<ECPOptimizer>
+    where
+        T: ECPIntegrator
+    {
+        let digest = MoleculeDigest::new(c_atm, c_bas, c_env);
+        self.get_ecp_with_digest::<T>(&digest, c_atm, c_natm, c_bas, c_nbas, c_env)
+    }
+
+    /// Same to [`Self::get_ecp`], with digest of molecule data computed by caller (before locking cache).
+    pub(crate) fn get_ecp_with_digest<T> (
+        &mut self, digest: &MoleculeDigest,
+        c_atm: &[i32], c_natm: i32, c_bas: &[i32], c_nbas: i32, c_env: &[f64]) -> Arc<ECPOptimizer>
+    where
+        T: ECPIntegrator
+    {
+        if let Some((key, opt)) = self.ecp.get(T::name()) {
+            if key == digest {
+                return opt.clone();
+            }
+        }
+        let opt = Arc::new(ECPOptimizer::new::<T>(c_atm, c_natm, c_bas, c_nbas, c_env));
+        self.ecp.insert(T::name(), (*digest, opt.clone()));
+        return opt;
+    }
+
+    /// Number of cached optimizers (libcint and ECP).
+    pub fn len(&self) -> usize {
+        self.cint.len() + self.ecp.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cint.is_empty() && self.ecp.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cint.clear();
+        self.ecp.clear();
+    }
+}
+
+/// Optimizer cache shared by copies (`Clone`) of the same molecule data.
+pub type SharedOptimizerCache = Arc<Mutex<OptimizerCache>>;
+
+/* #endregion */
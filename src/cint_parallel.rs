@@ -0,0 +1,123 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rayon::prelude::*;
+use rayon::{current_thread_index, ThreadPool};
+use crate::CINTR2CDATA;
+
+/// Where parallel integral engines run.
+///
+/// By default, integrals are evaluated on the current rayon pool (the global pool, or the pool of the
+/// calling thread if called inside `ThreadPool::install` or inside another rayon parallel loop).
+/// Applications that nest integral calls inside their own parallel loops may instead pass an explicit pool,
+/// or evaluate integrals sequentially on the calling thread.
+#[derive(Clone, Debug, Default)]
+pub enum Parallelism {
+    /// current rayon pool of the calling thread
+    #[default]
+    Current,
+    /// caller-supplied rayon pool
+    Pool(Arc<ThreadPool>),
+    /// sequential evaluation on the calling thread, with one cache
+    Sequential,
+}
+
+impl Parallelism {
+    /// Number of threads that parallel tasks may run on.
+    pub fn num_threads(&self) -> usize {
+        match self {
+            Parallelism::Current => rayon::current_num_threads(),
+            Parallelism::Pool(pool) => pool.current_num_threads(),
+            Parallelism::Sequential => 1,
+        }
+    }
+
+    /// Run `f(idx)` for each task `idx` in `0..n_task`.
+    pub(crate) fn for_each<OP> (&self, n_task: usize, f: OP)
+    where
+        OP: Fn(usize) + Sync + Send
+    {
+        match self {
+            Parallelism::Current => (0..n_task).into_par_iter().for_each(f),
+            Parallelism::Pool(pool) => pool.install(|| (0..n_task).into_par_iter().for_each(f)),
+            Parallelism::Sequential => (0..n_task).for_each(f),
+        }
+    }
+
+    /// Run `f(idx)` for each task `idx` in `order`, dispatching tasks in the given order.
+    ///
+    /// Each worker takes the next task from a shared cursor over `order`, so tasks are started strictly in the
+    /// given order (unlike splitting `order` by `par_iter`, where stealing threads start from the back halves);
+    /// together with `schedule_by_cost`, expensive tasks are started first.
+    pub(crate) fn for_each_ordered<OP> (&self, order: &[usize], f: OP)
+    where
+        OP: Fn(usize) + Sync + Send
+    {
+        let n_worker = self.num_threads().min(order.len());
+        let cursor = AtomicUsize::new(0);
+        let worker = |_| loop {
+            let pos = cursor.fetch_add(1, Ordering::Relaxed);
+            if pos >= order.len() { break; }
+            f(order[pos]);
+        };
+        match self {
+            Parallelism::Current => (0..n_worker).into_par_iter().for_each(worker),
+            Parallelism::Pool(pool) => pool.install(|| (0..n_worker).into_par_iter().for_each(worker)),
+            Parallelism::Sequential => order.iter().for_each(|&idx| f(idx)),
+        }
+    }
+
+    /// Collect `f(idx)` for each task `idx` in `0..n_task`, in order of tasks.
+    pub(crate) fn map_collect<R, OP> (&self, n_task: usize, f: OP) -> Vec<R>
+    where
+        R: Send, OP: Fn(usize) -> R + Sync + Send
+    {
+        match self {
+            Parallelism::Current => (0..n_task).into_par_iter().map(f).collect(),
+            Parallelism::Pool(pool) => pool.install(|| (0..n_task).into_par_iter().map(f).collect()),
+            Parallelism::Sequential => (0..n_task).map(f).collect(),
+        }
+    }
+}
+
+/// Thread-local buffers of parallel tasks (see dev note "Thread Local Buffer").
+///
+/// Buffers are retrieved by rayon thread index. Thread index is taken modulo number of buffers, so that
+/// threads of a pool other than the one buffers were sized for (or threads outside of rayon) still get a
+/// valid buffer; in that case threads sharing one buffer are serialized by its `Mutex`, but never race.
+pub(crate) struct ThreadBuffers<T> {
+    bufs: Vec<Mutex<Vec<T>>>,
+    size: usize,
+}
+
+impl<T> ThreadBuffers<T>
+where
+    T: Clone
+{
+    pub fn new(parallelism: &Parallelism, size: usize, value: T) -> Self {
+        let n_buf = parallelism.num_threads().max(1);
+        ThreadBuffers { bufs: (0..n_buf).map(|_| Mutex::new(vec![value.clone(); size])).collect(), size }
+    }
+
+    /// Length of each buffer.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn get(&self) -> MutexGuard<'_, Vec<T>> {
+        let thread_index = current_thread_index().unwrap_or(0) % self.bufs.len();
+        self.bufs[thread_index].lock().unwrap()
+    }
+}
+
+impl CINTR2CDATA {
+    /// Set where integrals are evaluated (current rayon pool, explicit pool, or sequential).
+    ///
+    /// This setting is copied by `Clone`, and also used by ECP integrals of this molecule.
+    pub fn set_parallelism(&mut self, parallelism: Parallelism) {
+        self.parallelism = parallelism;
+    }
+
+    pub fn parallelism(&self) -> &Parallelism {
+        &self.parallelism
+    }
+}
@@ -0,0 +1,143 @@
+use crate::cint_wrapper::*;
+use crate::cint_ao2mo::{npair, transform_pair};
+use crate::{CintType, CINTR2CDATA};
+
+/// Number of rows of `(pq|P)` handled by one parallel task when applying Coulomb metric.
+const RI_METRIC_ROW_CHUNK: usize = 256;
+
+/// Lower-triangular Cholesky factor `L` (`J = L L^T`) of f-contiguous symmetric positive-definite matrix `a` (n × n).
+///
+/// Upper triangle of output is zero. Panics if `a` is not positive definite.
+fn cholesky(mut a: Vec<f64>, n: usize) -> Vec<f64> {
+    for j in 0..n {
+        // left-looking: column j -= L[j, k] * column k, for k < j
+        let (head, tail) = a.split_at_mut(n * j);
+        for k in 0..j {
+            let l_jk = head[j + n * k];
+            if l_jk == 0. { continue; }
+            tail[j..n].iter_mut().zip(&head[(j + n * k)..(n + n * k)]).for_each(|(x, &y)| *x -= l_jk * y);
+        }
+        let d = tail[j];
+        if d.is_nan() || d <= 0. {
+            panic!("Coulomb metric is not positive definite at auxiliary function {j} (pivot {d:e}); auxiliary basis may be linearly dependent");
+        }
+        let d = d.sqrt();
+        tail[j] = d;
+        tail[(j + 1)..n].iter_mut().for_each(|x| *x /= d);
+        tail[..j].iter_mut().for_each(|x| *x = 0.);
+    }
+    return a;
+}
+
+impl CINTR2CDATA {
+    /// Cholesky factor `L` of Coulomb metric `J = (P|Q) = L L^T` (`int2c2e`) of auxiliary shells `aux_slice`.
+    ///
+    /// Output is f-contiguous and lower-triangular, with shape `(naux, naux)`. Panics if `J` is not positive definite
+    /// (linearly dependent auxiliary basis).
+    pub fn integral_ri_metric_cholesky(&self, aux_slice: [i32; 2]) -> (Vec<f64>, Vec<usize>) {
+        let (metric, shape) = self.integral_s1::<int2c2e>(Some(&[aux_slice, aux_slice]));
+        let naux = shape[0];
+        return (cholesky(metric, naux), vec![naux, naux]);
+    }
+
+    /// Three-index MO transformation for RI: `(ij|P)` to `(pq|P)` (such as `(ia|P)`).
+    ///
+    /// * `shl_slices` -
+    ///     shell slices `[i, j, P]` of `int3c2e`; auxiliary basis is usually appended to the molecular basis, and
+    ///     selected by the last slice.
+    /// * `coeff_i`, `coeff_j` -
+    ///     f-contiguous coefficient matrices of shape `(nao_i, nmo_i)` and `(nao_j, nmo_j)`, where `nao_i`, `nao_j`
+    ///     are number of AOs in shell slices. If both the coefficients and the shell slices of `i` and `j` are the
+    ///     same, MO pair `pq` is packed (`p <= q`, index `p + q * (q + 1) / 2`); otherwise MO pair is full (index
+    ///     `p + nmo_i * q`).
+    /// * `metric` -
+    ///     whether to apply Coulomb metric by its Cholesky factor ([`Self::integral_ri_metric_cholesky`]), giving
+    ///     `B_pq^Q = sum_P (pq|P) (L^{-T})_{PQ}`, so that `sum_Q B_pq^Q B_rs^Q = (pq|P) J^{-1}_{PQ} (Q|rs)`.
+    /// * `max_memory` -
+    ///     maximum bytes of AO integral of one auxiliary-shell batch.
+    ///
+    /// Output is f-contiguous with shape `(npair_pq, naux)`. AO integrals are evaluated in auxiliary-shell batches
+    /// ([`Self::integral_s1_batches`]), and transformed immediately; the AO 3-center tensor is never stored.
+    /// Metric is also applied batch by batch (forward substitution of `L`, in order of auxiliary functions), so
+    /// no second copy of output is made.
+    pub fn integral_ri_ao2mo(&self, shl_slices: &[[i32; 2]; 3], coeff_i: &[f64], coeff_j: &[f64], metric: bool, max_memory: usize) -> (Vec<f64>, Vec<usize>) {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by RI transformation");
+        }
+        let nao_i = *self.cgto_loc_slice_relative(&shl_slices[0]).last().unwrap();
+        let nao_j = *self.cgto_loc_slice_relative(&shl_slices[1]).last().unwrap();
+        let naux = *self.cgto_loc_slice_relative(&shl_slices[2]).last().unwrap();
+        let (nmo_i, nmo_j) = (Self::ao2mo_nmo(coeff_i, nao_i), Self::ao2mo_nmo(coeff_j, nao_j));
+        // (pq|P) is symmetric in pq only if both the shell slices and the coefficients are the same
+        let packed = shl_slices[0] == shl_slices[1] && coeff_i == coeff_j;
+        let npair_pq = npair(nmo_i, nmo_j, packed);
+
+        let chol = match metric {
+            true => Some(self.integral_ri_metric_cholesky(shl_slices[2]).0),
+            false => None,
+        };
+
+        let mut out = Vec::with_capacity(npair_pq * naux);
+        for (_, (eri, eri_shape)) in self.integral_s1_batches::<int3c2e>(Some(shl_slices), 2, max_memory) {
+            let naux_batch = eri_shape[2];
+            let out_batch = self.parallelism.map_collect(naux_batch, |p| {
+                let mut tmp = vec![0.; nao_i * nmo_j];
+                let m = &eri[nao_i * nao_j * p..nao_i * nao_j * (p + 1)];
+                transform_pair(m, nao_i, nao_j, coeff_i, nmo_i, coeff_j, nmo_j, packed, &mut tmp)
+            });
+            match &chol {
+                None => out_batch.into_iter().for_each(|col| out.extend(col)),
+                Some(chol) => {
+                    let out_batch = out_batch.into_iter().flatten().collect::<Vec<f64>>();
+                    let b_batch = self.ri_metric_forward_batch(&out, &out_batch, chol, naux, npair_pq);
+                    out.extend(b_batch);
+                },
+            }
+        }
+        return (out, vec![npair_pq, naux]);
+    }
+
+    /// Forward substitution `B L^T = X` of one batch of auxiliary functions.
+    ///
+    /// `b_prev` is `B` of previous auxiliary functions `(npair, q0)`, `x` is `(pq|P)` of this batch `(npair, nbatch)`;
+    /// output is `B` of this batch, all f-contiguous. Rows are independent, and handled by chunks in parallel.
+    fn ri_metric_forward_batch(&self, b_prev: &[f64], x: &[f64], chol: &[f64], naux: usize, npair: usize) -> Vec<f64> {
+        if npair == 0 {
+            return vec![];
+        }
+        let q0 = b_prev.len() / npair;
+        let nbatch = x.len() / npair;
+        let n_chunk = npair.div_ceil(RI_METRIC_ROW_CHUNK);
+        let tiles = self.parallelism.map_collect(n_chunk, |idx_chunk| {
+            let r0 = idx_chunk * RI_METRIC_ROW_CHUNK;
+            let nr = RI_METRIC_ROW_CHUNK.min(npair - r0);
+            let mut tile = vec![0.; nr * nbatch];
+            for q in 0..nbatch {
+                let (tile_prev, tile_q) = tile.split_at_mut(nr * q);
+                let col = &mut tile_q[..nr];
+                col.copy_from_slice(&x[(r0 + npair * q)..(r0 + nr + npair * q)]);
+                let row_l = |p: usize| chol[(q0 + q) + naux * p];
+                for p in (0..q0).filter(|&p| row_l(p) != 0.) {
+                    let l = row_l(p);
+                    col.iter_mut().zip(&b_prev[(r0 + npair * p)..(r0 + nr + npair * p)]).for_each(|(c, &b)| *c -= l * b);
+                }
+                for p in (0..q).filter(|&p| row_l(q0 + p) != 0.) {
+                    let l = row_l(q0 + p);
+                    col.iter_mut().zip(&tile_prev[(nr * p)..(nr * (p + 1))]).for_each(|(c, &b)| *c -= l * b);
+                }
+                let d = row_l(q0 + q);
+                col.iter_mut().for_each(|c| *c /= d);
+            }
+            tile
+        });
+        let mut out = vec![0.; npair * nbatch];
+        for (idx_chunk, tile) in tiles.iter().enumerate() {
+            let r0 = idx_chunk * RI_METRIC_ROW_CHUNK;
+            let nr = tile.len() / nbatch.max(1);
+            for q in 0..nbatch {
+                out[(r0 + npair * q)..(r0 + nr + npair * q)].copy_from_slice(&tile[(nr * q)..(nr * (q + 1))]);
+            }
+        }
+        return out;
+    }
+}
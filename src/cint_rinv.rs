@@ -0,0 +1,88 @@
+use rayon::prelude::*;
+use crate::cint_wrapper::*;
+use crate::cint::{ATM_SLOTS, PTR_ZETA, PTR_RINV_ZETA};
+use crate::cecp::AS_RINV_ORIG_ATOM;
+use crate::CINTR2CDATA;
+
+impl CINTR2CDATA {
+
+    /// Copy of `CINTR2CDATA` with rinv origin (and rinv zeta for finite nucleus) placed at atom `atm_id`.
+    ///
+    /// This is the same to PySCF's `with_rinv_at_nucleus`. Optimizer is not copied.
+    pub fn with_rinv_at_nucleus(&self, atm_id: usize) -> CINTR2CDATA {
+        let mut cint_data = self.clone_without_optimizer();
+        let coord = self.atom_coords()[atm_id];
+        let ptr_zeta = self.c_atm[atm_id * ATM_SLOTS as usize + PTR_ZETA as usize] as usize;
+        let zeta = if ptr_zeta == 0 { 0. } else { self.c_env[ptr_zeta] };
+        cint_data.set_rinv_origin(&coord);
+        cint_data.c_env[PTR_RINV_ZETA as usize] = zeta;
+        cint_data.c_env[AS_RINV_ORIG_ATOM as usize] = atm_id as f64;
+        return cint_data;
+    }
+
+    /// Integrals of rinv series (such as `int1e_rinv`, `int1e_iprinv`, `int1e_ipiprinv`),
+    /// with rinv origin placed at each atom, with no symmetry (s1).
+    ///
+    /// * `atoms` -
+    ///     Atom indices to be evaluated. `None` for all atoms.
+    /// * `shl_slices` -
+    ///     Shell slices of the two atomic orbital indices `i`, `j`.
+    ///
+    /// Output tensor is f-contiguous with shape `(i, j, atom)`, or `(i, j, comp, atom)` if the integrator
+    /// has more than one component. Atoms are evaluated in parallel on copies of `CINTR2CDATA`;
+    /// rinv origin of `self` is not modified.
+    pub fn integral_rinv_at_nuclei_s1<T> (&self, atoms: Option<&[usize]>, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
+    where
+        T: Integrator
+    {
+        if !T::name().contains("rinv") {
+            panic!("integrator {} is not one of rinv series", T::name());
+        }
+        let atoms = match atoms {
+            Some(atoms) => atoms.to_vec(),
+            None => (0..self.c_natm as usize).collect(),
+        };
+        let results = self.parallelism.map_collect(atoms.len(), |idx_atm| {
+            self.with_rinv_at_nucleus(atoms[idx_atm]).integral_s1::<T>(shl_slices)
+        });
+        let mut out_shape = match results.first() {
+            Some((_, shape)) => shape.clone(),
+            None => {
+                let shl_slices = shl_slices.map(|s| s.to_vec()).unwrap_or(vec![[0, self.c_nbas]; T::n_center()]);
+                let mut shape = self.cgto_shape::<T>(&shl_slices);
+                if T::n_comp() > 1 { shape.push(T::n_comp()); }
+                shape
+            },
+        };
+        out_shape.push(atoms.len());
+        let out = results.into_iter().flat_map(|(out, _)| out).collect::<Vec<f64>>();
+        return (out, out_shape);
+    }
+
+    /// Decomposition of nuclear attraction integrals by atoms, with no symmetry (s1).
+    ///
+    /// This is rinv series of integrals (see [`Self::integral_rinv_at_nuclei_s1`]) scaled by negative nuclear
+    /// charge of each atom. For example, summation of `int1e_rinv` decomposition over atoms gives `int1e_nuc`,
+    /// and summation of `int1e_iprinv` decomposition gives `int1e_ipnuc`.
+    ///
+    /// Output tensor is f-contiguous with shape `(i, j, atom)`, or `(i, j, comp, atom)`.
+    pub fn integral_nuc_by_atom_s1<T> (&self, atoms: Option<&[usize]>, shl_slices: Option<&[[i32; 2]]>) -> (Vec<f64>, Vec<usize>)
+    where
+        T: Integrator
+    {
+        let atoms = match atoms {
+            Some(atoms) => atoms.to_vec(),
+            None => (0..self.c_natm as usize).collect(),
+        };
+        let charges = self.atom_charges();
+        let (mut out, out_shape) = self.integral_rinv_at_nuclei_s1::<T>(Some(&atoms), shl_slices);
+        let size_per_atom = out_shape[..out_shape.len() - 1].iter().product::<usize>();
+        if size_per_atom > 0 {
+            out.chunks_mut(size_per_atom).zip(atoms.iter()).for_each(|(out_atom, &atm_id)| {
+                let charge = charges[atm_id];
+                out_atom.iter_mut().for_each(|v| *v *= -charge);
+            });
+        }
+        return (out, out_shape);
+    }
+}
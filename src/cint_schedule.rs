@@ -0,0 +1,59 @@
+use crate::cint::{ANG_OF, NPRIM_OF, NCTR_OF, BAS_SLOTS};
+use crate::CINTR2CDATA;
+
+/* #region cost model */
+
+/// Estimated relative cost of integral of one shell tuple, from `c_bas` of shells `shls`.
+///
+/// Cost is modeled as (number of primitive tuples) × (number of contracted cartesian functions) × (number of
+/// Rys roots), where number of Rys roots is `Σl / 2 + 1`. This is only used for ordering tasks, so that the
+/// absolute value is meaningless.
+pub(crate) fn shell_tuple_cost(c_bas: &[i32], shls: &[i32]) -> f64 {
+    let mut cost = 1.;
+    let mut l_sum = 0;
+    for &shl in shls {
+        let (l, weight) = shell_weight(c_bas, shl);
+        cost *= weight;
+        l_sum += l;
+    }
+    return cost * rys_roots_factor(l_sum);
+}
+
+/// Angular momentum and cost weight `n_prim × n_ctr × n_cart` of one shell.
+pub(crate) fn shell_weight(c_bas: &[i32], shl: i32) -> (i32, f64) {
+    let loc_bas = BAS_SLOTS as usize;
+    let bas = &c_bas[shl as usize * loc_bas..(shl as usize + 1) * loc_bas];
+    let l = bas[ANG_OF as usize];
+    let nprim = bas[NPRIM_OF as usize] as f64;
+    let nctr = bas[NCTR_OF as usize] as f64;
+    let ncart = ((l + 1) * (l + 2) / 2) as f64;
+    return (l, nprim * nctr * ncart);
+}
+
+/// Cost factor of number of Rys roots, for total angular momentum `l_sum` of shell tuple.
+#[inline]
+pub(crate) fn rys_roots_factor(l_sum: i32) -> f64 {
+    (l_sum / 2 + 1) as f64
+}
+
+/// Order of tasks by descending cost (stable for tasks of the same cost).
+///
+/// Parallel engines dispatch expensive tasks (high angular momentum or heavily contracted shells) first,
+/// so that cheap tasks fill idle threads at the end, instead of one expensive task delaying the whole call.
+pub(crate) fn schedule_by_cost(costs: &[f64]) -> Vec<usize> {
+    let mut order = (0..costs.len()).collect::<Vec<usize>>();
+    order.sort_by(|&a, &b| costs[b].total_cmp(&costs[a]));
+    return order;
+}
+
+/* #endregion */
+
+impl CINTR2CDATA {
+    /// Estimated relative cost of integral of shell tuple `shls` (see [`Self::integral_s1`] task scheduling).
+    ///
+    /// Cost is estimated from angular momentum (`ANG_OF`), number of primitives (`NPRIM_OF`) and number of
+    /// contractions (`NCTR_OF`) of shells; only ratio between costs of different tuples is meaningful.
+    pub fn shell_tuple_cost(&self, shls: &[i32]) -> f64 {
+        shell_tuple_cost(&self.c_bas, shls)
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use itertools::Itertools;
+use crate::cint_wrapper::*;
+use crate::{AoSym, CintType, CINTR2CDATA};
+
+/// Integral block of one shell tuple in [`BlockSparseTensor`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseBlock {
+    /// AO offsets of this block in each axis, relative to start of shell slices
+    pub ao_offsets: Vec<usize>,
+    /// f-contiguous shape of this block (including component, if more than one)
+    pub shape: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+/// Block-sparse integral tensor (s1 symmetry), keyed by shell tuples.
+///
+/// Only blocks with maximum absolute value not smaller than threshold are stored; other blocks are regarded as zero.
+/// Created by [`CINTR2CDATA::integral_s1_sparse`].
+#[derive(Clone, Debug)]
+pub struct BlockSparseTensor {
+    shl_slices: Vec<[i32; 2]>,
+    cgto_locs_rel: Vec<Vec<usize>>,
+    n_comp: usize,
+    threshold: f64,
+    blocks: BTreeMap<Vec<i32>, SparseBlock>,
+}
+
+impl BlockSparseTensor {
+    pub fn shl_slices(&self) -> &[[i32; 2]] {
+        &self.shl_slices
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Shape of dense tensor (including component, if more than one).
+    pub fn shape(&self) -> Vec<usize> {
+        let mut shape = self.cgto_locs_rel.iter().map(|loc| *loc.last().unwrap()).collect_vec();
+        if self.n_comp > 1 { shape.push(self.n_comp); }
+        shape
+    }
+
+    /// Number of stored (non-negligible) blocks.
+    pub fn n_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Number of all shell tuples, including negligible ones.
+    pub fn n_shell_tuples(&self) -> usize {
+        self.shl_slices.iter().map(|[shl_start, shl_stop]| (shl_stop - shl_start) as usize).product()
+    }
+
+    /// Iterate over stored blocks with their shell tuples (absolute shell indices).
+    pub fn iter(&self) -> impl Iterator<Item = (&[i32], &SparseBlock)> {
+        self.blocks.iter().map(|(shls, block)| (shls.as_slice(), block))
+    }
+
+    /// Block of shell tuple `shls` (absolute shell indices); `None` if it is negligible.
+    pub fn get_block(&self, shls: &[i32]) -> Option<&SparseBlock> {
+        self.blocks.get(shls)
+    }
+
+    /// Element at AO indices `ao_indices` (relative to start of shell slices) and component `comp`.
+    pub fn get(&self, ao_indices: &[usize], comp: usize) -> f64 {
+        assert_eq!(ao_indices.len(), self.shl_slices.len(), "number of AO indices differs from number of centers");
+        assert!(comp < self.n_comp, "component {comp} exceeds number of components {}", self.n_comp);
+        // shell containing each AO index
+        let shls = ao_indices.iter().zip(&self.cgto_locs_rel).zip(&self.shl_slices).map(|((&ao, loc), shl_slice)| {
+            assert!(ao < *loc.last().unwrap(), "AO index {ao} out of range");
+            let idx = loc.partition_point(|&p| p <= ao) - 1;
+            idx as i32 + shl_slice[0]
+        }).collect_vec();
+        match self.blocks.get(&shls) {
+            None => 0.,
+            Some(block) => {
+                let mut index = comp;
+                for n in (0..ao_indices.len()).rev() {
+                    index = index * block.shape[n] + (ao_indices[n] - block.ao_offsets[n]);
+                }
+                block.data[index]
+            },
+        }
+    }
+
+    /// Convert to dense f-contiguous tensor.
+    pub fn to_dense(&self) -> (Vec<f64>, Vec<usize>) {
+        let shape = self.shape();
+        let dims = self.cgto_locs_rel.iter().map(|loc| *loc.last().unwrap()).collect_vec();
+        let mut out = vec![0.; shape.iter().product()];
+        for block in self.blocks.values() {
+            let n_dim = dims.len();
+            // iterate elements of block in f-order
+            for (idx_block, &v) in block.data.iter().enumerate() {
+                let mut rem = idx_block;
+                let mut index = 0;
+                let mut stride = 1;
+                for n in 0..n_dim {
+                    index += (block.ao_offsets[n] + rem % block.shape[n]) * stride;
+                    rem /= block.shape[n];
+                    stride *= dims[n];
+                }
+                index += rem * stride;
+                out[index] = v;
+            }
+        }
+        (out, shape)
+    }
+
+    /// Contract first two AO indices with dense (f-contiguous) matrix `mat` of shape `(i, j)`.
+    ///
+    /// Gives f-contiguous tensor of remaining indices (including component); for example, `(ij|P) D_ij` of
+    /// `int3c2e` gives vector of auxiliary basis, and `(ij|kl) D_ij` of `int2e` gives Coulomb matrix.
+    pub fn contract_ij(&self, mat: &[f64]) -> (Vec<f64>, Vec<usize>) {
+        let shape = self.shape();
+        let (nao_i, nao_j) = (shape[0], shape[1]);
+        if mat.len() != nao_i * nao_j {
+            panic!("size of matrix {} is not the same to {nao_i} * {nao_j}", mat.len());
+        }
+        let out_shape = shape[2..].to_vec();
+        let dims = out_shape.clone();
+        let mut out = vec![0.; dims.iter().product()];
+        for block in self.blocks.values() {
+            let (di, dj) = (block.shape[0], block.shape[1]);
+            let (i0, j0) = (block.ao_offsets[0], block.ao_offsets[1]);
+            let rest_shape = &block.shape[2..];
+            let n_rest = rest_shape.iter().product::<usize>();
+            for idx_rest in 0..n_rest {
+                // index of remaining indices in output
+                let mut rem = idx_rest;
+                let mut index = 0;
+                let mut stride = 1;
+                for n in 0..rest_shape.len() {
+                    let offset = if n + 2 < block.ao_offsets.len() { block.ao_offsets[n + 2] } else { 0 };
+                    index += (offset + rem % rest_shape[n]) * stride;
+                    rem /= rest_shape[n];
+                    stride *= dims[n];
+                }
+                let data = &block.data[idx_rest * di * dj..(idx_rest + 1) * di * dj];
+                let mut val = 0.;
+                for j in 0..dj {
+                    for i in 0..di {
+                        val += data[i + di * j] * mat[(i0 + i) + nao_i * (j0 + j)];
+                    }
+                }
+                out[index] += val;
+            }
+        }
+        (out, out_shape)
+    }
+}
+
+impl CINTR2CDATA {
+    /// Evaluate integral (s1 symmetry) as block-sparse tensor, keeping only blocks of shell tuples with maximum
+    /// absolute value not smaller than `threshold`.
+    ///
+    /// This avoids dense output when most shell tuples are negligible (such as long molecules or large basis).
+    /// Blocks are evaluated in parallel by [`Self::for_each_block`].
+    pub fn integral_s1_sparse<T> (&self, shl_slices: Option<&[[i32; 2]]>, threshold: f64) -> BlockSparseTensor
+    where
+        T: Integrator
+    {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by block-sparse integral evaluation");
+        }
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices.to_vec(),
+            None => vec![[0, self.c_nbas]; T::n_center()],
+        };
+        let cgto_locs_rel = self.cgto_loc_slices_relative(&shl_slices);
+
+        let blocks = Mutex::new(BTreeMap::new());
+        self.for_each_block::<T, _>(AoSym::S1, Some(&shl_slices), |shls, ao_offsets, block| {
+            if block.data.iter().fold(0., |acc: f64, v| acc.max(v.abs())) >= threshold {
+                let block = SparseBlock { ao_offsets: ao_offsets.to_vec(), shape: block.shape.to_vec(), data: block.data.to_vec() };
+                blocks.lock().unwrap().insert(shls.to_vec(), block);
+            }
+        });
+
+        BlockSparseTensor {
+            shl_slices, cgto_locs_rel, n_comp: T::n_comp(), threshold,
+            blocks: blocks.into_inner().unwrap(),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use itertools::Itertools;
+use crate::cint_wrapper::*;
+use crate::cint_parallel::ThreadBuffers;
+use crate::cint_schedule::schedule_by_cost;
+use crate::{AoSym, CintType, CINTR2CDATA};
+
+/// Integral block of one shell tuple, handed out by [`CINTR2CDATA::for_each_block`].
+///
+/// Data is f-contiguous with `shape` `(di, dj, ..., comp)` (component dimension only if more than one).
+/// Data is borrowed from a task-local copy of the evaluated block, and is only valid inside the visitor.
+pub struct IntegralBlock<'a> {
+    pub data: &'a [f64],
+    pub shape: &'a [usize],
+}
+
+impl CINTR2CDATA {
+    /// Visit integral block of each shell tuple exactly once, in parallel, without materializing integral tensor.
+    ///
+    /// Visitor `op(shls, ao_offsets, block)` is called with shell tuple `shls` (absolute shell indices), AO offsets
+    /// of block in each axis (relative to start of `shl_slices`), and integral block. Optimizer, thread-local cache
+    /// and buffer are handled as in [`Self::integral_s1`]; expensive shell tuples are dispatched first.
+    ///
+    /// Thread-local cache and buffer are released before `op` is called, so `op` may use rayon itself (nested
+    /// `par_iter`, or other integral calls) without deadlock.
+    ///
+    /// For `AoSym::S2ij`, only shell pairs `shl_i <= shl_j` are visited; diagonal blocks (`shl_i == shl_j`) are
+    /// still full `(di, dj, ...)` blocks.
+    ///
+    /// ```no_run
+    /// let histogram = Mutex::new(vec![0; 20]);
+    /// cint_data.for_each_block::<int2e, _>(AoSym::S2ij, None, |shls, ao_offsets, block| {
+    ///     let vmax = block.data.iter().fold(0., |acc: f64, v| acc.max(v.abs()));
+    ///     /* ... */
+    /// });
+    /// ```
+    pub fn for_each_block<T, OP> (&self, aosym: AoSym, shl_slices: Option<&[[i32; 2]]>, op: OP)
+    where
+        T: Integrator, OP: Fn(&[i32], &[usize], &IntegralBlock<'_>) + Sync + Send
+    {
+        if self.cint_type == CintType::Spinor {
+            panic!("Spinor is not supported by block visitor");
+        }
+        let shl_slices = match shl_slices {
+            Some(shl_slices) => shl_slices.to_vec(),
+            None => vec![[0, self.c_nbas]; T::n_center()],
+        };
+        let ctx = self.eval_context::<T, f64>();
+        match aosym {
+            AoSym::S1 => ctx.check_shl_slices::<T>(&shl_slices).unwrap(),
+            AoSym::S2ij => { ctx.cgto_shape_s2ij::<T>(&shl_slices).unwrap(); },
+        }
+
+        let n_comp = T::n_comp();
+        let n_center = T::n_center();
+        let cgto_locs_rel = ctx.cgto_loc_slices_relative(&shl_slices);
+
+        // thread-local cache and buffer
+        let cache_size = ctx.size_of_cache::<T>(&shl_slices);
+        let buf_size = ctx.size_of_buffer::<T>(&shl_slices);
+        let thread_cache = ThreadBuffers::new(&self.parallelism, cache_size, 0.);
+        let thread_buf = ThreadBuffers::new(&self.parallelism, buf_size, 0.);
+
+        // tasks: outer two shells; inner shells are iterated in each task
+        let index_outer = [n_center - 2, n_center - 1].map(|n| (shl_slices[n][1] - shl_slices[n][0]) as usize);
+        let outer_shls = |idx_outer: usize| [
+            (idx_outer % index_outer[0]) as i32 + shl_slices[n_center - 2][0],
+            (idx_outer / index_outer[0]) as i32 + shl_slices[n_center - 1][0],
+        ];
+        let inner_shls = match n_center {
+            2 => vec![vec![]],
+            _ => shl_slices[..n_center - 2].iter()
+                .map(|&[shl_start, shl_stop]| shl_start..shl_stop)
+                .multi_cartesian_product().collect_vec(),
+        };
+
+        let task_costs = (0..(index_outer[0] * index_outer[1])).map(|idx_outer| {
+            let shls = outer_shls(idx_outer);
+            match (aosym, n_center) {
+                (AoSym::S2ij, 2) if shls[0] > shls[1] => 0.,
+                _ => ctx.shell_tuple_cost(&shls),
+            }
+        }).collect_vec();
+        let task_order = schedule_by_cost(&task_costs);
+
+        self.parallelism.for_each_ordered(&task_order, |idx_outer| {
+            // task-local copy of block; visitor is called without holding thread-local buffers, since it may use
+            // rayon itself, and a blocked worker could then steal another task of this loop on the same thread
+            let mut block = Vec::with_capacity(buf_size);
+            for inner in &inner_shls {
+                let shls = inner.iter().cloned().chain(outer_shls(idx_outer)).collect_vec();
+                if aosym == AoSym::S2ij && shls[0] > shls[1] {
+                    continue;
+                }
+                let mut shape = shls.iter().map(|&shl| ctx.cgto_size(shl)).collect_vec();
+                if n_comp > 1 { shape.push(n_comp); }
+                let size = shape.iter().product::<usize>();
+                {
+                    let mut cache = thread_cache.get();
+                    let mut buf = thread_buf.get();
+                    unsafe { ctx.integral_block::<f64>(&mut buf, &shls, &[], &mut cache); }
+                    block.clear();
+                    block.extend_from_slice(&buf[..size]);
+                }
+                let ao_offsets = shls.iter().enumerate()
+                    .map(|(n, &shl)| cgto_locs_rel[n][(shl - shl_slices[n][0]) as usize]).collect_vec();
+                op(&shls, &ao_offsets, &IntegralBlock { data: &block, shape: &shape });
+            }
+        });
+    }
+}
@@ -1879,28 +1879,34 @@ impl_integrator!(
 /* The following code will possibly to be deprecated. */
 
 use crate::CINTR2CDATA;
+use crate::cint_optimizer::CINTOptimizer;
+use std::ptr::null_mut;
 
 /// optimizer macro rules
 macro_rules! impl_intor_optimizer {
     ($name:expr, $optim_rust:ident, $coptim_rust:ident, $optim_cint:ident) => {
         pub fn $optim_rust(&mut self){
             self.cint_del_optimizer_rust();
+            let mut c_opt = null_mut();
             unsafe {
                 cint::$optim_cint(
-                    &mut self.c_opt,
+                    &mut c_opt,
                     self.c_atm.as_mut_ptr(), self.c_natm,
                     self.c_bas.as_mut_ptr(), self.c_nbas,
                     self.c_env.as_mut_ptr());
+                self.c_opt = CINTOptimizer::from_raw(c_opt);
             }
         }
         pub fn $coptim_rust(&mut self){
             self.cint_del_optimizer_rust();
+            let mut c_opt = null_mut();
             unsafe {
                 cint::$optim_cint(
-                    &mut self.c_opt,
+                    &mut c_opt,
                     self.c_atm.as_mut_ptr(), self.c_natm,
                     self.c_bas.as_mut_ptr(), self.c_nbas,
                     self.c_env.as_mut_ptr());
+                self.c_opt = CINTOptimizer::from_raw(c_opt);
             }
         }
     };
@@ -102,7 +102,8 @@ use std::collections::btree_map::Range;
 use std::process::exit;
 use std::{os::raw::c_int, ptr::null, ptr::null_mut};
 use std::mem::ManuallyDrop;
-use cint::{PTR_COMMON_ORIG, PTR_RINV_ORIG};
+use cint::{PTR_COMMON_ORIG, PTR_RINV_ORIG, PTR_EXPCUTOFF};
+use cint::{ATM_SLOTS, CHARGE_OF, PTR_COORD, PTR_FRAC_CHARGE};
 use itertools::Itertools;
 
 pub mod cint;
@@ -111,10 +112,33 @@ mod utilities;
 pub mod cint_wrapper;
 pub mod cecp_wrapper;
 pub mod cint_crafter;
+pub mod cint_context;
+pub mod cint_optimizer;
+pub mod cint_parallel;
+pub mod cint_schedule;
+pub mod cint_batch;
+pub mod cint_estimate;
+pub mod cint_ooc;
+pub mod cint_mmap;
+pub mod cint_sparse;
+pub mod cint_visitor;
+pub mod cint_ao2mo;
+pub mod cint_ri;
+pub mod cint_hcore;
+pub mod cint_nuclear;
+pub mod cint_grad1e;
+pub mod cint_grad2e;
+pub mod cint_fcidump;
+pub mod cint_grids;
+pub mod cint_rinv;
+pub mod cint_env;
+pub mod cint_multipole;
 pub mod cecp_crafter;
 pub mod prelude;
 
 use crate::cint::{CINTOpt,CINTdel_optimizer};
+use crate::cint_optimizer::{CINTOptimizer, SharedOptimizerCache};
+use crate::cint_parallel::Parallelism;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum CintType {
@@ -123,6 +147,42 @@ pub enum CintType {
    Spinor,
 }
 
+/// Symmetry of AO indices of integral output.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AoSym {
+    /// no symmetry
+    S1,
+    /// symmetric in first two AO indices `(i, j)`, packed by lower triangle
+    S2ij,
+}
+
+/// Presets of exponent cutoff (`c_env[PTR_EXPCUTOFF]`) for primitive-pair screening.
+///
+/// Primitive pairs with exponential prefactor smaller than `exp(-cutoff)` are neglected by libcint.
+/// Larger cutoff gives more accurate integrals but more primitive pairs to be evaluated.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExpCutoff {
+    /// cutoff 100, neglects prefactor below ~1e-44
+    Tight,
+    /// cutoff 60 (libcint default), neglects prefactor below ~1e-26
+    Default,
+    /// cutoff 40 (smallest value accepted by libcint), neglects prefactor below ~4e-18
+    Loose,
+    /// user-defined cutoff (values smaller than 40 are treated as 40 by libcint)
+    Custom(f64),
+}
+
+impl ExpCutoff {
+    pub fn value(&self) -> f64 {
+        match self {
+            ExpCutoff::Tight => 100.,
+            ExpCutoff::Default => 60.,
+            ExpCutoff::Loose => 40.,
+            ExpCutoff::Custom(cutoff) => *cutoff,
+        }
+    }
+}
+
 pub enum IJOPT {
     Ovlp,
     Kinetic,
@@ -142,9 +202,8 @@ pub enum IP3C2E {
     IP2,
 }
 
-#[derive(Clone)]
 pub struct CINTR2CDATA {
-    c_opt: *mut CINTOpt,
+    c_opt: CINTOptimizer,
     c_natm: i32,
     c_nbas: i32,
     c_necp: i32,
@@ -153,13 +212,35 @@ pub struct CINTR2CDATA {
     c_bas: Vec<i32>,
     c_env: Vec<f64>,
     c_ecp: Vec<i32>,
+    opt_cache: SharedOptimizerCache,
+    parallelism: Parallelism,
+}
+
+/// Copy of molecule data. Optimizer set by `optimizer::<T>` is not copied (libcint does not provide copy of
+/// optimizer), while optimizer cache is shared.
+impl Clone for CINTR2CDATA {
+    fn clone(&self) -> Self {
+        CINTR2CDATA {
+            c_opt: CINTOptimizer::null(),
+            c_natm: self.c_natm,
+            c_nbas: self.c_nbas,
+            c_necp: self.c_necp,
+            cint_type: self.cint_type,
+            c_atm: self.c_atm.clone(),
+            c_bas: self.c_bas.clone(),
+            c_env: self.c_env.clone(),
+            c_ecp: self.c_ecp.clone(),
+            opt_cache: self.opt_cache.clone(),
+            parallelism: self.parallelism.clone(),
+        }
+    }
 }
 
 impl CINTR2CDATA {
     /// create a new, empty CINTR2CDATA.
     pub fn new() -> CINTR2CDATA {
         CINTR2CDATA {
-            c_opt: null_mut(),
+            c_opt: CINTOptimizer::null(),
             c_natm: 0,
             c_nbas: 0,
             c_necp: 0,
@@ -168,9 +249,14 @@ impl CINTR2CDATA {
             c_bas: Vec::new(),
             c_env: Vec::new(),
             c_ecp: Vec::new(),
+            opt_cache: SharedOptimizerCache::default(),
+            parallelism: Parallelism::default(),
         }
     }
 
+    /// Set common origin in `c_env`.
+    ///
+    /// This permanently changes `c_env`; consider `with_common_origin` or `env_guard` for scoped usage.
     pub fn set_common_origin(&mut self, comm_orig: &[f64]) {
         self.c_env[PTR_COMMON_ORIG as usize..PTR_COMMON_ORIG as usize+3].iter_mut().zip(comm_orig.iter()).for_each(|(a,b)| {*a = *b});
     }
@@ -179,6 +265,9 @@ impl CINTR2CDATA {
         self.c_env[PTR_COMMON_ORIG as usize..PTR_COMMON_ORIG as usize+3].try_into().unwrap()
     }
 
+    /// Set rinv origin in `c_env`.
+    ///
+    /// This permanently changes `c_env`; consider `with_rinv_origin` or `env_guard` for scoped usage.
     pub fn set_rinv_origin(&mut self, comm_orig: &[f64]) {
         self.c_env[PTR_RINV_ORIG as usize..PTR_RINV_ORIG as usize+3].iter_mut().zip(comm_orig.iter()).for_each(|(a,b)| {*a = *b});
     }
@@ -187,6 +276,49 @@ impl CINTR2CDATA {
         self.c_env[PTR_RINV_ORIG as usize..PTR_RINV_ORIG as usize+3].try_into().unwrap()
     }
 
+    /// Cartesian coordinates (in Bohr) of atoms.
+    pub fn atom_coords(&self) -> Vec<[f64; 3]> {
+        self.c_atm.chunks(ATM_SLOTS as usize).map(|atm| {
+            let ptr = atm[PTR_COORD as usize] as usize;
+            self.c_env[ptr..ptr+3].try_into().unwrap()
+        }).collect()
+    }
+
+    /// Nuclear charges of atoms, in the same way libcint evaluates nuclear attraction.
+    ///
+    /// Fractional charge (`PTR_FRAC_CHARGE`) is used if it is set; otherwise absolute value of `CHARGE_OF`.
+    pub fn atom_charges(&self) -> Vec<f64> {
+        self.c_atm.chunks(ATM_SLOTS as usize).map(|atm| {
+            match atm[PTR_FRAC_CHARGE as usize] {
+                0 => atm[CHARGE_OF as usize].abs() as f64,
+                ptr => self.c_env[ptr as usize],
+            }
+        }).collect()
+    }
+
+    /// Set exponent cutoff for primitive-pair screening (`c_env[PTR_EXPCUTOFF]`).
+    ///
+    /// This affects libcint integrals evaluated afterwards. ECP integrals are not affected, since the ECP code
+    /// uses its own fixed cutoff (`EXPCUTOFF` 39 in `nr_ecp.h`).
+    ///
+    /// Returns magnitude `exp(-cutoff)` of the largest neglected primitive-pair prefactor; this is not an
+    /// error estimate of integrals.
+    pub fn set_exp_cutoff(&mut self, cutoff: ExpCutoff) -> f64 {
+        self.c_env[PTR_EXPCUTOFF as usize] = cutoff.value();
+        self.get_exp_cutoff_precision()
+    }
+
+    /// Effective exponent cutoff used by libcint (value 0 in `c_env` refers to libcint default 60).
+    pub fn get_exp_cutoff(&self) -> f64 {
+        let cutoff = self.c_env[PTR_EXPCUTOFF as usize];
+        if cutoff == 0. { ExpCutoff::Default.value() } else { cutoff.max(ExpCutoff::Loose.value()) }
+    }
+
+    /// Magnitude `exp(-cutoff)` of the largest neglected primitive-pair prefactor (not an error estimate).
+    pub fn get_exp_cutoff_precision(&self) -> f64 {
+        (-self.get_exp_cutoff()).exp()
+    }
+
     pub fn set_cint_type(&mut self, ctype: &CintType) {
         self.cint_type = *ctype;
     }
@@ -200,7 +332,8 @@ impl CINTR2CDATA {
         self.c_env = env.clone();
         self.c_natm = natm;
         self.c_nbas = nbas;
-        self.c_opt = null_mut();
+        self.c_opt = CINTOptimizer::null();
+        self.opt_cache = SharedOptimizerCache::default();
     }
 
     pub fn initial_r2c_with_ecp(&mut self,
@@ -218,17 +351,12 @@ impl CINTR2CDATA {
     }
 
     pub fn cint_del_optimizer_rust(&mut self) {
-        unsafe{
-            CINTdel_optimizer(&mut self.c_opt);
-        }
+        self.c_opt = CINTOptimizer::null();
     }
 
     pub fn cint1e_ecp_optimizer_rust(&mut self){
         self.cint_del_optimizer_rust();
         //self.cint_init_2e_optimizer_rust();
-        unsafe {
-            self.c_opt = std::ptr::null::<CINTOpt>() as *mut CINTOpt                            
-        }
     }
     pub fn cint_cgto_rust(&self, index: i32) -> i32 {
         let mut dim: i32;
@@ -258,13 +386,13 @@ impl CINTR2CDATA {
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
                     self.c_env.as_ptr(),
-                    self.c_opt, null_mut()),
+                    self.c_opt.as_ptr(), null_mut()),
                 CintType::Cartesian => cint::int2c2e_cart(
                     c_buf, null(), c_shls,
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
                     self.c_env.as_ptr(),
-                    self.c_opt, null_mut()),
+                    self.c_opt.as_ptr(), null_mut()),
                 CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
             };
             //println!("debug 1 {}", &c_buf.read());
@@ -290,13 +418,13 @@ impl CINTR2CDATA {
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
                     self.c_env.as_ptr(),
-                    self.c_opt, null_mut()),
+                    self.c_opt.as_ptr(), null_mut()),
                 CintType::Cartesian => cint::int2c2e_ip1_cart(
                     c_buf, null(), c_shls,
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
                     self.c_env.as_ptr(),
-                    self.c_opt, null_mut()),
+                    self.c_opt.as_ptr(), null_mut()),
                 CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
             };
             //println!("debug 1 {}", &c_buf.read());
@@ -323,13 +451,13 @@ impl CINTR2CDATA {
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
                     self.c_env.as_ptr(),
-                    self.c_opt, null_mut()),
+                    self.c_opt.as_ptr(), null_mut()),
                 CintType::Cartesian => cint::int3c2e_cart(
                     c_buf, null(), c_shls,
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
                     self.c_env.as_ptr(),
-                    self.c_opt, null_mut()),
+                    self.c_opt.as_ptr(), null_mut()),
                 CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
             };
             //println!("debug 1 {}", &c_buf.read());
@@ -362,13 +490,13 @@ impl CINTR2CDATA {
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
                     self.c_env.as_ptr(),
-                    self.c_opt, null_mut()),
+                    self.c_opt.as_ptr(), null_mut()),
                 CintType::Cartesian => cint::int2e_cart(
                     c_buf, null(), c_shls,
                     self.c_atm.as_ptr(), self.c_natm,
                     self.c_bas.as_ptr(), self.c_nbas,
                     self.c_env.as_ptr(),
-                    self.c_opt, null_mut()),
+                    self.c_opt.as_ptr(), null_mut()),
                 CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
             };
             //println!("debug 1 {}", &c_buf.read());
@@ -420,13 +548,13 @@ impl CINTR2CDATA {
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Cartesian => cint::int1e_ovlp_cart(
                             c_buf, null(), c_shls,
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
                     }
                 },
@@ -437,13 +565,13 @@ impl CINTR2CDATA {
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Cartesian => cint::int1e_kin_cart(
                             c_buf, null(), c_shls,
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
                     }
                 },
@@ -454,13 +582,13 @@ impl CINTR2CDATA {
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Cartesian => cint::int1e_nuc_cart(
                             c_buf, null(), c_shls,
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
                     }
                 },
@@ -509,13 +637,13 @@ impl CINTR2CDATA {
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Cartesian => cint::int1e_ipovlp_cart(
                             c_buf, null(), c_shls,
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
                     }
                 },
@@ -526,13 +654,13 @@ impl CINTR2CDATA {
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Cartesian => cint::int1e_ipkin_cart(
                             c_buf, null(), c_shls,
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
                     }
                 },
@@ -543,13 +671,13 @@ impl CINTR2CDATA {
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Cartesian => cint::int1e_ipnuc_cart(
                             c_buf, null(), c_shls,
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
                     }
                 },
@@ -560,13 +688,13 @@ impl CINTR2CDATA {
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Cartesian => cint::int1e_iprinv_cart(
                             c_buf, null(), c_shls,
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
                     }
                 }
@@ -610,13 +738,13 @@ impl CINTR2CDATA {
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Cartesian => cint::int3c2e_ip1_cart(
                             c_buf, null(), c_shls,
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
                     }},
                 IP3C2E::IP2 => {
@@ -626,13 +754,13 @@ impl CINTR2CDATA {
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Cartesian => cint::int3c2e_ip2_cart(
                             c_buf, null(), c_shls,
                             self.c_atm.as_ptr(), self.c_natm,
                             self.c_bas.as_ptr(), self.c_nbas,
                             self.c_env.as_ptr(),
-                            self.c_opt, null_mut()),
+                            self.c_opt.as_ptr(), null_mut()),
                         CintType::Spinor => panic!("Spinor GTOs are not yet supported"),
                     }},
             };
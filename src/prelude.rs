@@ -1,4 +1,15 @@
 pub use crate::cint_wrapper::*;
 pub use crate::cecp_wrapper::*;
 pub use crate::CINTR2CDATA;
-pub use crate::CintType;
\ No newline at end of file
+pub use crate::CintType;
+pub use crate::AoSym;
+pub use crate::ExpCutoff;
+pub use crate::cint_multipole::MultipoleOrigin;
+pub use crate::cint_context::CINTEvalContext;
+pub use crate::cint_parallel::Parallelism;
+pub use crate::cint_estimate::IntegralEstimate;
+pub use crate::cint_ooc::{OutOfCoreIntegral, OOCBlock};
+pub use crate::cint_mmap::{MmapIntegral, MmapLayout};
+pub use crate::cint_sparse::{BlockSparseTensor, SparseBlock};
+pub use crate::cint_visitor::IntegralBlock;
+pub use crate::cint_fcidump::FCIDump;
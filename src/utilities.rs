@@ -1,3 +1,4 @@
+use std::marker::PhantomData;
 use num_complex::*;
 
 pub trait FF64: Sized + Send + Sync + Copy + Clone {
@@ -10,11 +11,68 @@ impl FF64 for Complex<f64> {
     fn zero() -> Self { Complex::new(0.0, 0.0) }
 }
 
-pub(crate) unsafe fn cast_mut_slice<T> (slc: &[T]) -> &mut [T] {
-    let len = slc.len();
-    let ptr = slc.as_ptr() as *mut T;
-    let mut mut_vector = std::slice::from_raw_parts_mut(ptr, len);
-    return mut_vector;
+/// Mutable output buffer shared by threads, where each thread writes its own disjoint elements.
+///
+/// The buffer is exclusively borrowed (`&'a mut [T]`) during the lifetime of this handle, and elements
+/// are only accessed by raw pointers derived from that borrow; no reference (`&[T]` or `&mut [T]`) to the
+/// buffer is created while the handle is shared between threads. Callers of unsafe functions should
+/// guarantee that
+/// - each element is written by at most one thread (regions of different parallel tasks never overlap);
+/// - elements are not read through this handle during the parallel region.
+///
+/// Bounds are checked by this handle: [`DisjointMut::write`] checks the index, and [`DisjointMut::ptr_at`]
+/// checks the whole block that is to be written through the returned pointer.
+pub(crate) struct DisjointMut<'a, T> {
+    ptr: *mut T,
+    len: usize,
+    _marker: PhantomData<&'a mut [T]>,
+}
+
+unsafe impl<T> Send for DisjointMut<'_, T> where T: Send {}
+unsafe impl<T> Sync for DisjointMut<'_, T> where T: Send {}
+
+impl<'a, T> DisjointMut<'a, T> {
+    pub fn new(slc: &'a mut [T]) -> Self {
+        DisjointMut { ptr: slc.as_mut_ptr(), len: slc.len(), _marker: PhantomData }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Raw pointer to a block starting at `offset` and spanning `extent` elements (see [`block_extent`]).
+    ///
+    /// The whole block is bound checked, so writes within `extent` elements from this pointer never go
+    /// beyond the buffer; such writes should still follow invariants of [`DisjointMut`].
+    #[inline(always)]
+    pub fn ptr_at(&self, offset: usize, extent: usize) -> *mut T {
+        assert!(offset.checked_add(extent).is_some_and(|end| end <= self.len),
+            "block at offset {offset} with extent {extent} exceeds buffer length {}", self.len);
+        unsafe { self.ptr.add(offset) }
+    }
+
+    /// Write `value` to element `index` (bound checked).
+    #[inline(always)]
+    pub unsafe fn write(&self, index: usize, value: T) {
+        assert!(index < self.len, "index {index} exceeds buffer length {}", self.len);
+        self.ptr.add(index).write(value);
+    }
+}
+
+/// Number of elements spanned by f-contiguous block of shape `block_shape` in buffer of shape `shape`,
+/// counted from the first element of the block.
+#[inline(always)]
+pub(crate) fn block_extent(block_shape: &[usize], shape: &[usize]) -> usize {
+    if block_shape.contains(&0) {
+        return 0;
+    }
+    let mut extent = 1;
+    let mut stride = 1;
+    for (&n_block, &n) in block_shape.iter().zip(shape) {
+        extent += (n_block - 1) * stride;
+        stride *= n;
+    }
+    return extent;
 }
 
 #[inline(always)]
@@ -72,7 +130,7 @@ pub(crate) fn get_f_index_5d_s2ij(indices: &[usize; 5], shape: &[usize; 4]) -> u
 }
 
 #[inline(always)]
-pub(crate) fn copy_3d_s2ij_offdiag<T> (out: &mut [T], out_offsets: &[usize; 3], out_s2ij_shape: &[usize; 2], buf: &[T], buf_shape: &[usize; 3])
+pub(crate) unsafe fn copy_3d_s2ij_offdiag<T> (out: &DisjointMut<T>, out_offsets: &[usize; 3], out_s2ij_shape: &[usize; 2], buf: &[T], buf_shape: &[usize; 3])
 where
     T: Copy
 {
@@ -83,14 +141,14 @@ where
                 let buf_indices = [i, j, c];
                 let out_index = get_f_index_3d_s2ij(&out_indices, out_s2ij_shape);
                 let buf_index = get_f_index_3d(&buf_indices, buf_shape);
-                out[out_index] = buf[buf_index];
+                out.write(out_index, buf[buf_index]);
             }
         }
     }
 }
 
 #[inline(always)]
-pub(crate) fn copy_3d_s2ij_diag<T> (out: &mut [T], out_offsets: &[usize; 3], out_s2ij_shape: &[usize; 2], buf: &[T], buf_shape: &[usize; 3])
+pub(crate) unsafe fn copy_3d_s2ij_diag<T> (out: &DisjointMut<T>, out_offsets: &[usize; 3], out_s2ij_shape: &[usize; 2], buf: &[T], buf_shape: &[usize; 3])
 where
     T: Copy
 {
@@ -101,14 +159,14 @@ where
                 let buf_indices = [i, j, c];
                 let out_index = get_f_index_3d_s2ij(&out_indices, out_s2ij_shape);
                 let buf_index = get_f_index_3d(&buf_indices, buf_shape);
-                out[out_index] = buf[buf_index];
+                out.write(out_index, buf[buf_index]);
             }
         }
     }
 }
 
 #[inline(always)]
-pub(crate) fn copy_4d_s2ij_offdiag<T> (out: &mut [T], out_offsets: &[usize; 4], out_s2ij_shape: &[usize; 3], buf: &[T], buf_shape: &[usize; 4])
+pub(crate) unsafe fn copy_4d_s2ij_offdiag<T> (out: &DisjointMut<T>, out_offsets: &[usize; 4], out_s2ij_shape: &[usize; 3], buf: &[T], buf_shape: &[usize; 4])
 where
     T: Copy
 {
@@ -120,7 +178,7 @@ where
                     let buf_indices = [i, j, k, c];
                     let out_index = get_f_index_4d_s2ij(&out_indices, out_s2ij_shape);
                     let buf_index = get_f_index_4d(&buf_indices, buf_shape);
-                    out[out_index] = buf[buf_index];
+                    out.write(out_index, buf[buf_index]);
                 }
             }
         }
@@ -128,7 +186,7 @@ where
 }
 
 #[inline(always)]
-pub(crate) fn copy_4d_s2ij_diag<T> (out: &mut [T], out_offsets: &[usize; 4], out_s2ij_shape: &[usize; 3], buf: &[T], buf_shape: &[usize; 4])
+pub(crate) unsafe fn copy_4d_s2ij_diag<T> (out: &DisjointMut<T>, out_offsets: &[usize; 4], out_s2ij_shape: &[usize; 3], buf: &[T], buf_shape: &[usize; 4])
 where
     T: Copy
 {
@@ -140,7 +198,7 @@ where
                     let buf_indices = [i, j, k, c];
                     let out_index = get_f_index_4d_s2ij(&out_indices, out_s2ij_shape);
                     let buf_index = get_f_index_4d(&buf_indices, buf_shape);
-                    out[out_index] = buf[buf_index];
+                    out.write(out_index, buf[buf_index]);
                 }
             }
         }
@@ -148,7 +206,7 @@ where
 }
 
 #[inline(always)]
-pub(crate) fn copy_5d_s2ij_offdiag<T> (out: &mut [T], out_offsets: &[usize; 5], out_s2ij_shape: &[usize; 4], buf: &[T], buf_shape: &[usize; 5])
+pub(crate) unsafe fn copy_5d_s2ij_offdiag<T> (out: &DisjointMut<T>, out_offsets: &[usize; 5], out_s2ij_shape: &[usize; 4], buf: &[T], buf_shape: &[usize; 5])
 where
     T: Copy
 {
@@ -161,7 +219,7 @@ where
                         let buf_indices = [i, j, k, l, c];
                         let out_index = get_f_index_5d_s2ij(&out_indices, out_s2ij_shape);
                         let buf_index = get_f_index_5d(&buf_indices, buf_shape);
-                        out[out_index] = buf[buf_index];
+                        out.write(out_index, buf[buf_index]);
                     }
                 }
             }
@@ -170,7 +228,7 @@ where
 }
 
 #[inline(always)]
-pub(crate) fn copy_5d_s2ij_diag<T> (out: &mut [T], out_offsets: &[usize; 5], out_s2ij_shape: &[usize; 4], buf: &[T], buf_shape: &[usize; 5])
+pub(crate) unsafe fn copy_5d_s2ij_diag<T> (out: &DisjointMut<T>, out_offsets: &[usize; 5], out_s2ij_shape: &[usize; 4], buf: &[T], buf_shape: &[usize; 5])
 where
     T: Copy
 {
@@ -183,7 +241,7 @@ where
                         let buf_indices = [i, j, k, l, c];
                         let out_index = get_f_index_5d_s2ij(&out_indices, out_s2ij_shape);
                         let buf_index = get_f_index_5d(&buf_indices, buf_shape);
-                        out[out_index] = buf[buf_index];
+                        out.write(out_index, buf[buf_index]);
                     }
                 }
             }
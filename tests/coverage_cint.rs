@@ -15,7 +15,7 @@ mod coverage_cint {
     #[test]
     #[should_panic]
     fn cover_panic_type_inconsistent() {
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let shl_slices = vec![[0, 5]; int3c2e_ip1::n_center()];
         let mut out_shape = cint_data.cgto_shape_s2ij::<int3c2e_ip1>(&shl_slices).unwrap();
         if int3c2e_ip1::n_comp() > 1 { out_shape.push(int3c2e_ip1::n_comp()); }
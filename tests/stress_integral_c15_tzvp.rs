@@ -9,7 +9,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int3c2e_s1_full() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let (out, _) = cint_data.integral_s1::<int3c2e>(None);
         println!("Elapsed: {:.3?}", now.elapsed());
@@ -25,7 +25,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int3c2e_s1_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[7, 275], [12, 129], [5, 264]];
         let (out, _) = cint_data.integral_s1::<int3c2e>(Some(&shl_slices));
@@ -42,7 +42,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int3c2e_ip1_s1_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[7, 275], [12, 129], [5, 264]];
         let (out, _) = cint_data.integral_s1::<int3c2e_ip1>(Some(&shl_slices));
@@ -59,7 +59,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int2c2e_ip1_s1_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[7, 275], [5, 264]];
         let (out, _) = cint_data.integral_s1::<int2c2e_ip1>(Some(&shl_slices));
@@ -76,7 +76,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int2e_ip1ip2_s1_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[7, 30], [5, 52], [58, 89], [125, 156]];
         let (out, _) = cint_data.integral_s1::<int2e_ip1ip2>(Some(&shl_slices));
@@ -93,7 +93,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int3c2e_s2ij_full() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let (out, _) = cint_data.integral_s2ij::<int3c2e>(None);
         println!("Elapsed: {:.3?}", now.elapsed());
@@ -109,7 +109,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int3c2e_ip2_s2ij_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[0, 275], [0, 275], [7, 264]];
         let (out, _) = cint_data.integral_s2ij::<int3c2e_ip2>(Some(&shl_slices));
@@ -126,7 +126,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int2e_ip2_s2ij_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[10, 50], [10, 50], [127, 168], [215, 272]];
         let (out, _) = cint_data.integral_s2ij::<int2e_ip2>(Some(&shl_slices));
@@ -143,7 +143,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int2c2e_s2ij_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[10, 283], [10, 283]];
         let (out, _) = cint_data.integral_s2ij::<int2c2e>(Some(&shl_slices));
@@ -160,7 +160,7 @@ mod stress_integral_c15_tzvp {
     #[test]
     fn test_int3c2e_ip2_s2ij_slice_ndarray() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[0, 275], [0, 275], [7, 264]];
         let (out, out_shape) = cint_data.integral_s2ij::<int3c2e_ip2>(Some(&shl_slices));
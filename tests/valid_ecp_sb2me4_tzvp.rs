@@ -9,7 +9,7 @@ mod valid_ecp_sb2me4_tzvp {
 
     #[test]
     fn test_ECPscalar() {
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let (out, _) = cint_data.integral_ecp_s1::<ECPscalar>(None);
         println!("Elapsed: {:.3?}", now.elapsed());
@@ -24,7 +24,7 @@ mod valid_ecp_sb2me4_tzvp {
 
     #[test]
     fn test_ECPscalar_ipnuc() {
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[7, 93], [14, 121]];
         let (out, _) = cint_data.integral_ecp_s1::<ECPscalar_ipnuc>(Some(&shl_slices));
@@ -40,7 +40,7 @@ mod valid_ecp_sb2me4_tzvp {
 
     #[test]
     fn test_ECPscalar_ipiprinv() {
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[7, 93], [14, 121]];
         let (out, _) = cint_data.integral_ecp_s1::<ECPscalar_ipiprinv>(Some(&shl_slices));
@@ -56,7 +56,7 @@ mod valid_ecp_sb2me4_tzvp {
 
     #[test]
     fn test_ECPscalar_ignuc() {
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[7, 93], [14, 121]];
         let (out, _) = cint_data.integral_ecp_s1::<ECPscalar_ignuc>(Some(&shl_slices));
@@ -87,6 +87,53 @@ mod valid_ecp_sb2me4_tzvp {
             (out * scale).sum(), -77.65227858432043, max_relative=1e-10);
     }
 
+    #[test]
+    fn test_hcore() {
+        let mut cint_data = initialize();
+        for cint_type in [CintType::Spheric, CintType::Cartesian] {
+            cint_data.set_cint_type(&cint_type);
+            let (out, shape) = cint_data.hcore();
+            let (kin, _) = cint_data.integral_s1::<int1e_kin>(None);
+            let (nuc, _) = cint_data.integral_s1::<int1e_nuc>(None);
+            let (ecp, shape_ref) = cint_data.integral_ecp_s1::<ECPscalar>(None);
+            assert_eq!(shape, shape_ref);
+            itertools::izip!(out, kin, nuc, ecp).for_each(|(v, t, n, e)| assert_relative_eq!(v, t + n + e, max_relative=1e-12, epsilon=1e-12));
+        }
+    }
+
+    #[test]
+    fn test_nuclear_repulsion_ecp() {
+        let cint_data = initialize();
+        // effective charge of Sb with ECP core electrons removed
+        let charges = cint_data.atom_charges();
+        assert_eq!(charges[0], 23.);
+        let coords = cint_data.atom_coords();
+        let natm = coords.len();
+        let energy_ref = (0..natm).cartesian_product(0..natm).filter(|(a, b)| b < a).map(|(a, b)| {
+            let dist = (0..3).map(|t| (coords[a][t] - coords[b][t]).powi(2)).sum::<f64>().sqrt();
+            charges[a] * charges[b] / dist
+        }).sum::<f64>();
+        assert_relative_eq!(cint_data.nuclear_repulsion_energy(), energy_ref, max_relative=1e-14);
+        // hard-coded reference with effective charge 23 of Sb (1253.04 if bare nuclear charge 51 were used)
+        assert_relative_eq!(cint_data.nuclear_repulsion_energy(), 501.438694679485, max_relative=1e-12);
+        let (grad, _) = cint_data.nuclear_repulsion_gradient();
+        (0..3).for_each(|t| assert_abs_diff_eq!((0..natm).map(|a| grad[t + 3 * a]).sum::<f64>(), 0., epsilon=1e-10));
+    }
+
+    #[test]
+    fn test_grad1e_ecp_translational_invariance() {
+        let cint_data = initialize();
+        let (hcore, shape) = cint_data.hcore();
+        let nao = shape[0];
+        let natm = cint_data.atom_coords().len();
+        let dm = (0..nao * nao).map(|idx| ((idx % nao + idx / nao) as f64 * 0.13).cos()).collect_vec();
+        // gradient of hcore contracted with any density matrix sums to zero over atoms (ECP terms included)
+        let (grad, shape) = cint_data.contract_hcore_deriv(&dm);
+        assert_eq!(shape, vec![3, natm]);
+        let scale = hcore.iter().zip(&dm).map(|(h, d)| (h * d).abs()).sum::<f64>();
+        (0..3).for_each(|t| assert_abs_diff_eq!((0..natm).map(|a| grad[t + 3 * a]).sum::<f64>() / scale, 0., epsilon=1e-8));
+    }
+
     fn initialize() -> CINTR2CDATA {
         let c_atm = vec![
             [23, 20,  4, 23,  0,  0],
@@ -0,0 +1,208 @@
+#[cfg(test)]
+mod valid_grids_h2o_tzvp {
+    use std::time::Instant;
+    use itertools::Itertools;
+    use rest_libcint::prelude::*;
+    use ndarray::prelude::*;
+    use approx::*;
+
+    #[test]
+    fn test_int1e_grids_ip_shape() {
+
+        let cint_data = initialize();
+        let grids = vec![[0.1, 0.2, 0.3], [-1.0, 0.5, 2.0], [3.0, -2.0, 1.0], [0.0, 0.0, -4.0], [1.5, 1.5, 1.5]];
+        let now = Instant::now();
+        let (out, out_shape) = cint_data.integral_grids_s1::<int1e_grids_ip>(&grids, None);
+        println!("Elapsed: {:.3?}", now.elapsed());
+        assert_eq!(out_shape, [5, 43, 43, 3]);
+        assert_eq!(out.len(), 5 * 43 * 43 * 3);
+
+        let shl_slices = vec![[3, 15], [6, 12]];
+        let (_, out_shape) = cint_data.integral_grids_s1::<int1e_grids>(&grids, Some(&shl_slices));
+        assert_eq!(out_shape, [5, 26, 20]);
+    }
+
+    #[test]
+    fn test_int1e_grids_nuc() {
+
+        // -sum_A Z_A <i|1/|r-R_A||j> should be the same to int1e_nuc (point-charge nuclei)
+        let cint_data = initialize();
+        let grids = vec![
+            [0.0, 0.0, 0.0],
+            [1.7763425570911580, 0.0, 0.0],
+            [-0.44476065664656128, 0.0, 1.7197618551510188]];
+        let charges = [8., 1., 1.];
+        // more grids than one batch, to check batch offsets
+        let grids = grids.repeat(100);
+        let charges = charges.repeat(100);
+        let now = Instant::now();
+        let (out, out_shape) = cint_data.integral_grids_s1::<int1e_grids>(&grids, None);
+        println!("Elapsed: {:.3?}", now.elapsed());
+
+        let out = Array::from_shape_vec(out_shape.f(), out).unwrap().into_dimensionality::<Ix3>().unwrap();
+        let charges = Array::from_vec(charges);
+        let nuc_grids = - charges.dot(&out.into_shape((300, 43 * 43)).unwrap()) / 100.;
+        let (nuc, _) = cint_data.integral_s1::<int1e_nuc>(None);
+        let nuc = Array::from_vec(nuc);
+        assert_relative_eq!(
+            (nuc_grids - &nuc).mapv(f64::abs).sum(), 0., epsilon=1e-8);
+    }
+
+    #[test]
+    fn test_electrostatic_potential() {
+
+        let cint_data = initialize();
+        let grids = Array::linspace(-3., 3., 300).iter().map(|&x| [x, 0.5 * x + 0.3, 1.0 - 0.2 * x]).collect_vec();
+        let dm = density_matrix();
+        let now = Instant::now();
+        let esp = cint_data.electrostatic_potential(&grids, dm.as_slice_memory_order().unwrap());
+        println!("Elapsed: {:.3?}", now.elapsed());
+
+        // reference by full (grid, i, j) tensor
+        let (out, out_shape) = cint_data.integral_grids_s1::<int1e_grids>(&grids, None);
+        let out = Array::from_shape_vec(out_shape.f(), out).unwrap().into_dimensionality::<Ix3>().unwrap();
+        let elec = out.into_shape((300, 43 * 43)).unwrap().dot(&dm.t().as_standard_layout().into_shape(43 * 43).unwrap());
+        let nuc = Array::from_vec(cint_data.nuclear_potential_on_grids(&grids));
+        let esp_ref = nuc - elec;
+        assert_relative_eq!(
+            (Array::from_vec(esp) - esp_ref).mapv(f64::abs).sum(), 0., epsilon=1e-8);
+    }
+
+    #[test]
+    fn test_electric_field() {
+
+        // field should be negative gradient of potential
+        let cint_data = initialize();
+        let grids = vec![[0.1, 0.2, 0.3], [-1.0, 0.5, 2.0], [3.0, -2.0, 1.0], [0.0, 0.0, -4.0], [1.5, 1.5, 1.5]];
+        let dm = density_matrix();
+        let dm = dm.as_slice_memory_order().unwrap();
+        let field = cint_data.electric_field(&grids, dm);
+        let h = 1e-4;
+        for t in 0..3 {
+            let grids_p = grids.iter().map(|&g| { let mut g = g; g[t] += h; g }).collect_vec();
+            let grids_m = grids.iter().map(|&g| { let mut g = g; g[t] -= h; g }).collect_vec();
+            let esp_p = cint_data.electrostatic_potential(&grids_p, dm);
+            let esp_m = cint_data.electrostatic_potential(&grids_m, dm);
+            for g in 0..grids.len() {
+                let field_fd = - (esp_p[g] - esp_m[g]) / (2. * h);
+                assert_relative_eq!(field[g + grids.len() * t], field_fd, epsilon=1e-6);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "size of density matrix")]
+    fn test_electric_field_dm_size() {
+        let cint_data = initialize();
+        let grids = vec![[0.1, 0.2, 0.3]];
+        let dm = density_matrix();
+        let dm = dm.as_slice_memory_order().unwrap();
+        cint_data.electric_field(&grids, &dm[..dm.len() - 1]);
+    }
+
+    #[test]
+    fn test_set_grids_in_env_guard() {
+
+        // grids replaced inside guard are placed at the same end of `c_env`; they should be restored on drop
+        let mut cint_data = initialize();
+        let grids = vec![[0.1, 0.2, 0.3], [-1.0, 0.5, 2.0], [3.0, -2.0, 1.0]];
+        cint_data.set_grids(&grids);
+        let (out_ref, _) = cint_data.integral_grids_s1::<int1e_grids>(&grids, None);
+        {
+            let mut guard = cint_data.env_guard();
+            guard.set_grids(&[[0., 0., 1.]]);
+            assert_eq!(guard.ngrids(), 1);
+        }
+        assert_eq!(cint_data.ngrids(), 3);
+        assert_eq!(cint_data.get_grids(), grids);
+        let (out, _) = cint_data.integral_grids_s1::<int1e_grids>(&grids, None);
+        assert_eq!(out, out_ref);
+    }
+
+    /// Symmetric matrix for testing, not necessarily physical density matrix.
+    fn density_matrix() -> Array2<f64> {
+        let dm = Array::linspace(-0.1, 0.2, 43 * 43).into_shape((43, 43)).unwrap();
+        let dm = &dm + &dm.t();
+        dm.as_standard_layout().to_owned()
+    }
+
+    fn initialize() -> CINTR2CDATA {
+        // mol = gto.Mole(atom="O; H 1 0.94; H 1 0.94 2 104.5", basis="def2-TZVP").build()
+        let c_atm = vec![
+            [ 8, 20,  1, 23,  0,  0],
+            [ 1, 24,  1, 27,  0,  0],
+            [ 1, 28,  1, 31,  0,  0],
+        ];
+        let c_bas = vec![
+            [ 0,  0,  6,  1,  0, 44, 50,  0],
+            [ 0,  0,  2,  1,  0, 56, 58,  0],
+            [ 0,  0,  1,  1,  0, 60, 61,  0],
+            [ 0,  0,  1,  1,  0, 62, 63,  0],
+            [ 0,  0,  1,  1,  0, 64, 65,  0],
+            [ 0,  1,  4,  1,  0, 66, 70,  0],
+            [ 0,  1,  1,  1,  0, 74, 75,  0],
+            [ 0,  1,  1,  1,  0, 76, 77,  0],
+            [ 0,  2,  1,  1,  0, 78, 79,  0],
+            [ 0,  2,  1,  1,  0, 80, 81,  0],
+            [ 0,  3,  1,  1,  0, 82, 83,  0],
+            [ 1,  0,  3,  1,  0, 32, 35,  0],
+            [ 1,  0,  1,  1,  0, 38, 39,  0],
+            [ 1,  0,  1,  1,  0, 40, 41,  0],
+            [ 1,  1,  1,  1,  0, 42, 43,  0],
+            [ 2,  0,  3,  1,  0, 32, 35,  0],
+            [ 2,  0,  1,  1,  0, 38, 39,  0],
+            [ 2,  0,  1,  1,  0, 40, 41,  0],
+            [ 2,  1,  1,  1,  0, 42, 43,  0],
+        ];
+        let c_env = vec![
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+            1.7763425570911580e+00,  0.0000000000000000e+00,
+            0.0000000000000000e+00,  0.0000000000000000e+00,
+           -4.4476065664656128e-01,  0.0000000000000000e+00,
+            1.7197618551510188e+00,  0.0000000000000000e+00,
+            3.4061340999999999e+01,  5.1235746000000004e+00,
+            1.1646626000000000e+00,  9.0618446120248586e-01,
+            1.6354784928239057e+00,  2.4145128304249659e+00,
+            3.2723041000000003e-01,  1.0930883523645869e+00,
+            1.0307241000000000e-01,  4.5959135109675275e-01,
+            8.0000000000000004e-01,  2.2072263710762661e+00,
+            2.7032382631000000e+04,  4.0523871392000001e+03,
+            9.2232722709999996e+02,  2.6124070989000001e+02,
+            8.5354641350999998e+01,  3.1035035245000000e+01,
+            3.0481181169845928e+00,  5.6914576328642115e+00,
+            9.7338835744432526e+00,  1.5238733819733028e+01,
+            2.0843228934131737e+01,  2.2391049059992991e+01,
+            1.2260860728000001e+01,  4.9987076005000004e+00,
+            1.0568131135849375e+01,  3.3391469496791393e+00,
+            1.1703108158000000e+00,  2.8427648592056753e+00,
+            4.6474740994000002e-01,  1.4220922112658689e+00,
+            1.8504536357000001e-01,  7.1280983010446131e-01,
+            6.3274954801000000e+01,  1.4627049379000001e+01,
+            4.4501223455999996e+00,  1.5275799646999999e+00,
+            6.2570323747894276e+00,  6.9268656235998423e+00,
+            6.0323599265415284e+00,  3.5035168827833356e+00,
+            5.2935117942999999e-01,  1.3172379939563448e+00,
+            1.7478421270000000e-01,  3.2969483673949351e-01,
+            2.3140000000000001e+00,  1.1328313432935008e+01,
+            6.4500000000000002e-01,  1.2113199965714336e+00,
+            1.4279999999999999e+00,  4.3969226782656516e+00
+        ];
+
+        let c_atm = c_atm.iter().map(|&v| v.to_vec()).collect_vec();
+        let c_bas = c_bas.iter().map(|&v| v.to_vec()).collect_vec();
+        let mut cint_data = CINTR2CDATA::new();
+        cint_data.initial_r2c(&c_atm, c_atm.len() as i32, &c_bas, c_bas.len() as i32, &c_env);
+        return cint_data;
+    }
+}
@@ -10,7 +10,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int3c2e_s1_full() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let (out, _) = cint_data.integral_s1::<int3c2e>(None);
         println!("Elapsed: {:.3?}", now.elapsed());
@@ -26,7 +26,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int3c2e_s1_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[3, 15], [6, 12], [2, 18]];
         let (out, _) = cint_data.integral_s1::<int3c2e>(Some(&shl_slices));
@@ -43,7 +43,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int3c2e_ip1_s1_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[3, 15], [6, 12], [2, 18]];
         let (out, _) = cint_data.integral_s1::<int3c2e_ip1>(Some(&shl_slices));
@@ -60,7 +60,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int2c2e_ip1_s1_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[3, 15], [6, 12]];
         let (out, _) = cint_data.integral_s1::<int2c2e_ip1>(Some(&shl_slices));
@@ -77,7 +77,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int2e_ip1ip2_s1_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[3, 15], [6, 12], [2, 18], [7, 11]];
         let (out, _) = cint_data.integral_s1::<int2e_ip1ip2>(Some(&shl_slices));
@@ -94,7 +94,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int3c2e_s2ij_full() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let (out, _) = cint_data.integral_s2ij::<int3c2e>(None);
         println!("Elapsed: {:.3?}", now.elapsed());
@@ -110,7 +110,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int3c2e_ip2_s2ij_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[0, 15], [0, 15], [6, 12]];
         let (out, _) = cint_data.integral_s2ij::<int3c2e_ip2>(Some(&shl_slices));
@@ -127,7 +127,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int2e_ip2_s2ij_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[3, 15], [3, 15], [6, 12], [7, 11]];
         let (out, _) = cint_data.integral_s2ij::<int2e_ip2>(Some(&shl_slices));
@@ -144,7 +144,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int2c2e_s2ij_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[3, 15], [3, 15]];
         let (out, _) = cint_data.integral_s2ij::<int2c2e>(Some(&shl_slices));
@@ -178,7 +178,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int1e_ignuc_spinor_s1_full() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let (out, out_shape) = cint_data.integral_spinor_s1::<int1e_ignuc>(None);
         println!("out_shape: {:?}", out_shape);
@@ -197,7 +197,7 @@ mod valid_integral_h2o_tzvp {
     #[test]
     fn test_int2e_ip1ip2_spinor_s1_slice() {
 
-        let mut cint_data = initialize();
+        let cint_data = initialize();
         let now = Instant::now();
         let shl_slices = vec![[1, 7], [6, 12], [15, 19], [10, 14]];
         let (out, _) = cint_data.integral_spinor_s1::<int2e_ip1ip2>(Some(&shl_slices));
@@ -213,7 +213,1007 @@ mod valid_integral_h2o_tzvp {
         assert_relative_eq!(res.im(), -41.555332416720624, max_relative=1e-10);
     }
 
+    #[test]
+    fn test_int3c2e_s1_exp_cutoff() {
+
+        let mut cint_data = initialize();
+        assert_eq!(cint_data.get_exp_cutoff(), 60.);
+        let precision = cint_data.set_exp_cutoff(ExpCutoff::Tight);
+        assert_eq!(cint_data.get_exp_cutoff(), 100.);
+        assert_relative_eq!(precision, 3.720075976020836e-44, max_relative=1e-10);
+        let (out, _) = cint_data.integral_s1::<int3c2e>(None);
+        let out = Array::from_vec(out);
+        assert_relative_eq!(
+            out.sum(), 5372.255349662842, max_relative=1e-10);
+
+        // custom cutoff is stored as given in c_env (not snapped to a preset); values below 40 are clamped by libcint
+        let precision = cint_data.set_exp_cutoff(ExpCutoff::Custom(50.));
+        assert_eq!(cint_data.get_exp_cutoff(), 50.);
+        assert_relative_eq!(precision, (-50_f64).exp(), max_relative=1e-14);
+        let precision = cint_data.set_exp_cutoff(ExpCutoff::Custom(10.));
+        assert_eq!(cint_data.get_exp_cutoff(), 40.);
+        assert_relative_eq!(precision, (-40_f64).exp(), max_relative=1e-14);
+        cint_data.set_exp_cutoff(ExpCutoff::Default);
+        assert_eq!(cint_data.get_exp_cutoff(), 60.);
+    }
+
+    #[test]
+    fn test_int1e_nuc_by_atom() {
+
+        let cint_data = initialize();
+        let now = Instant::now();
+        let (out, out_shape) = cint_data.integral_nuc_by_atom_s1::<int1e_rinv>(None, None);
+        println!("Elapsed: {:.3?}", now.elapsed());
+        assert_eq!(out_shape, [43, 43, 3]);
+        assert_eq!(cint_data.get_rinv_origin(), [0., 0., 0.]);
+
+        // reference of `-Z_A int1e_rinv` at each nucleus, see `pyscf_integral_h2o_tzvp.ipynb`
+        let scale = Array::linspace(-1., 1., out.len());
+        assert_relative_eq!(
+            (Array::from_vec(out.clone()) * scale).sum(), 734.8619098578159, max_relative=1e-10);
+        let out = Array::from_shape_vec(out_shape.f(), out).unwrap().into_dimensionality::<Ix3>().unwrap();
+        for (atm_id, ref_sum) in [-987.3260291577503, -79.57528003229936, -79.18409059434065].into_iter().enumerate() {
+            assert_relative_eq!(out.index_axis(Axis(2), atm_id).sum(), ref_sum, max_relative=1e-10);
+        }
+        let (nuc, _) = cint_data.clone().integral_s1::<int1e_nuc>(None);
+        let nuc = Array::from_shape_vec((43, 43).f(), nuc).unwrap();
+        assert_relative_eq!(
+            (out.sum_axis(Axis(2)) - nuc).mapv(f64::abs).sum(), 0., epsilon=1e-10);
+    }
+
+    #[test]
+    fn test_int1e_iprinv_by_atom_slice() {
+
+        let cint_data = initialize();
+        let shl_slices = vec![[3, 15], [6, 12]];
+        let (out, out_shape) = cint_data.integral_nuc_by_atom_s1::<int1e_iprinv>(None, Some(&shl_slices));
+        assert_eq!(out_shape, [26, 20, 3, 3]);
+
+        let out = Array::from_shape_vec(out_shape.f(), out).unwrap().into_dimensionality::<Ix4>().unwrap();
+        let (ipnuc, ipnuc_shape) = cint_data.clone().integral_s1::<int1e_ipnuc>(Some(&shl_slices));
+        let ipnuc = Array::from_shape_vec(ipnuc_shape.f(), ipnuc).unwrap().into_dimensionality::<Ix3>().unwrap();
+        assert_relative_eq!(
+            (out.sum_axis(Axis(3)) - ipnuc).mapv(f64::abs).sum(), 0., epsilon=1e-10);
+
+        let (out, out_shape) = cint_data.integral_rinv_at_nuclei_s1::<int1e_iprinv>(Some(&[2, 0]), Some(&shl_slices));
+        assert_eq!(out_shape, [26, 20, 3, 2]);
+        assert_eq!(out.len(), 26 * 20 * 3 * 2);
+    }
+
+    #[test]
+    fn test_int1e_r_with_common_origin() {
+
+        let mut cint_data = initialize();
+        let orig = [0.3, -0.2, 1.1];
+        let (r0, _) = cint_data.integral_s1::<int1e_r>(None);
+        let (ovlp, _) = cint_data.integral_s1::<int1e_ovlp>(None);
+        let (r1, out_shape) = cint_data.with_common_origin(&orig, |data| data.integral_s1::<int1e_r>(None));
+        assert_eq!(cint_data.get_common_origin(), [0., 0., 0.]);
+
+        // <i|r - O|j> = <i|r|j> - O <i|j>
+        let r0 = Array::from_shape_vec(out_shape.clone().f(), r0).unwrap().into_dimensionality::<Ix3>().unwrap();
+        let r1 = Array::from_shape_vec(out_shape.clone().f(), r1).unwrap().into_dimensionality::<Ix3>().unwrap();
+        let ovlp = Array::from_shape_vec((43, 43).f(), ovlp).unwrap();
+        for t in 0..3 {
+            let r1_ref = &r0.index_axis(Axis(2), t) - orig[t] * &ovlp;
+            assert_relative_eq!(
+                (&r1.index_axis(Axis(2), t) - &r1_ref).mapv(f64::abs).sum(), 0., epsilon=1e-10);
+        }
+
+        // env guard restores common origin, and also GTO type
+        {
+            let mut guard = cint_data.env_guard();
+            guard.set_common_origin(&orig);
+            guard.set_grids(&[[0., 0., 1.]]);
+            let (r2, _) = guard.integral_s1::<int1e_r>(None);
+            let r2 = Array::from_shape_vec(out_shape.f(), r2).unwrap().into_dimensionality::<Ix3>().unwrap();
+            assert_relative_eq!(
+                (r2 - &r1).mapv(f64::abs).sum(), 0., epsilon=1e-10);
+            guard.set_cint_type(&CintType::Cartesian);
+        }
+        assert_eq!(cint_data.get_common_origin(), [0., 0., 0.]);
+        assert_eq!(cint_data.ngrids(), 0);
+        let (_, ovlp_shape) = cint_data.integral_s1::<int1e_ovlp>(None);
+        assert_eq!(ovlp_shape, vec![43, 43]);
+    }
+
+    #[test]
+    fn test_int2c2e_with_omega() {
+
+        // long-range (erf) and short-range (erfc) coulomb sum up to full-range coulomb
+        let cint_data = initialize();
+        let (full, _) = cint_data.clone().integral_s1::<int2c2e>(None);
+        let (lr, _) = cint_data.with_omega(0.4, |data| data.integral_s1::<int2c2e>(None));
+        let (sr, _) = cint_data.with_omega(-0.4, |data| data.integral_s1::<int2c2e>(None));
+        assert_eq!(cint_data.get_omega(), 0.);
+        let full = Array::from_vec(full);
+        assert_relative_eq!(
+            (Array::from_vec(lr) + Array::from_vec(sr) - &full).mapv(f64::abs).sum(), 0., epsilon=1e-8);
+    }
+
+    #[test]
+    fn test_shared_cint_data_concurrent() {
+
+        // different integrators and GTO types on one shared `CINTR2CDATA`
+        let cint_data = std::sync::Arc::new(initialize());
+        let (ovlp_ref, _) = cint_data.integral_s1::<int1e_ovlp>(None);
+        let (kin_ref, _) = cint_data.integral_s2ij::<int1e_kin>(None);
+        let (spinor_ref, _) = cint_data.integral_spinor_s1::<int1e_ignuc>(None);
+        let (ovlp, kin, spinor) = std::thread::scope(|s| {
+            let ovlp = s.spawn(|| cint_data.integral_s1::<int1e_ovlp>(None).0);
+            let kin = s.spawn(|| cint_data.integral_s2ij::<int1e_kin>(None).0);
+            let spinor = s.spawn(|| cint_data.integral_spinor_s1::<int1e_ignuc>(None).0);
+            (ovlp.join().unwrap(), kin.join().unwrap(), spinor.join().unwrap())
+        });
+        assert_eq!(ovlp, ovlp_ref);
+        assert_eq!(kin, kin_ref);
+        assert_eq!(spinor, spinor_ref);
+        assert_eq!(cint_data.cgto_loc().last(), Some(&43));
+    }
+
+    #[test]
+    fn test_integral_batches() {
+
+        let cint_data = initialize();
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int3c2e>(None);
+
+        // batches along auxiliary shells, each batch no larger than 1/4 of full output
+        let max_memory = out_ref.len() * 8 / 4;
+        let batches = cint_data.integral_s1_batches::<int3c2e>(None, 2, max_memory);
+        assert!(batches.len() >= 4);
+        let mut out = vec![];
+        let mut naux = 0;
+        for (shl_slices, (out_batch, shape_batch)) in batches {
+            assert!(out_batch.len() * 8 <= max_memory);
+            assert_eq!(shl_slices[..2], [[0, 19], [0, 19]]);
+            naux += shape_batch[2];
+            out.extend(out_batch);
+        }
+        assert_eq!(naux, shape_ref[2]);
+        assert_eq!(out, out_ref);
+
+        // batches along first axis with multiple components
+        let (out_ref, _) = cint_data.integral_s1::<int1e_ipovlp>(None);
+        let out_ref = Array::from_shape_vec((43, 43, 3).f(), out_ref).unwrap();
+        for (shl_slices, (out_batch, shape_batch)) in cint_data.integral_s1_batches::<int1e_ipovlp>(None, 0, 2000) {
+            let cgto_loc = cint_data.cgto_loc();
+            let [p0, p1] = shl_slices[0].map(|shl| cgto_loc[shl as usize]);
+            let out_batch = Array::from_shape_vec(shape_batch.f(), out_batch).unwrap();
+            assert_eq!(out_batch, out_ref.slice(s![p0..p1, .., ..]).into_dyn());
+        }
+    }
+
+    #[test]
+    fn test_integral_s1_to_file() {
+
+        let cint_data = initialize();
+        let path = std::env::temp_dir().join(format!("rest_libcint_ooc_{}.bin", std::process::id()));
+
+        // 3-center, blocked by auxiliary shells
+        let shl_slices = [[0, 19], [2, 15], [0, 19]];
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int3c2e>(Some(&shl_slices));
+        let out_ref = Array::from_shape_vec(shape_ref.f(), out_ref).unwrap();
+        let ooc = cint_data.integral_s1_to_file::<int3c2e>(&path, Some(&shl_slices)).unwrap();
+        assert_eq!(ooc.blocks().len(), 19);
+        let cgto_loc = cint_data.cgto_loc();
+        for (idx_block, block) in ooc.blocks().iter().enumerate() {
+            let (out, shape) = ooc.read_block(idx_block).unwrap();
+            let shl_k = block.outer_shls[0];
+            let [k0, k1] = [cgto_loc[shl_k as usize], cgto_loc[shl_k as usize + 1]];
+            let out = Array::from_shape_vec(shape.f(), out).unwrap();
+            assert_eq!(out, out_ref.slice(s![.., .., k0..k1]).into_dyn());
+        }
+
+        // read back from reopened file
+        let reopened = OutOfCoreIntegral::open(&path).unwrap();
+        assert_eq!(reopened.blocks(), ooc.blocks());
+        assert_eq!(reopened.shl_slices(), &shl_slices);
+        let idx_block = reopened.find_block(&[7]).unwrap();
+        assert_eq!(reopened.read_block(idx_block).unwrap(), ooc.read_block(idx_block).unwrap());
+
+        // 4-center with components, blocked by shell pairs (k, l)
+        let shl_slices = [[0, 5], [0, 19], [3, 6], [10, 13]];
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int2e_ip1>(Some(&shl_slices));
+        let out_ref = Array::from_shape_vec(shape_ref.f(), out_ref).unwrap();
+        let ooc = cint_data.integral_s1_to_file::<int2e_ip1>(&path, Some(&shl_slices)).unwrap();
+        assert_eq!(ooc.blocks().len(), 9);
+        let cgto_loc_k = cint_data.cgto_loc_slice_relative(&shl_slices[2]);
+        let cgto_loc_l = cint_data.cgto_loc_slice_relative(&shl_slices[3]);
+        for (idx_block, block) in ooc.blocks().iter().enumerate() {
+            let (out, shape) = ooc.read_block(idx_block).unwrap();
+            let [shl_k, shl_l] = [block.outer_shls[0] - 3, block.outer_shls[1] - 10].map(|v| v as usize);
+            let out = Array::from_shape_vec(shape.f(), out).unwrap();
+            let out_ref = out_ref.slice(s![.., .., cgto_loc_k[shl_k]..cgto_loc_k[shl_k + 1], cgto_loc_l[shl_l]..cgto_loc_l[shl_l + 1], ..]);
+            assert_eq!(out, out_ref.into_dyn());
+        }
+
+        // untrusted header: truncated data, and number of blocks exceeding file
+        use std::io::{Seek, SeekFrom, Write};
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_len(file.metadata().unwrap().len() - 8).unwrap();
+        let err = OutOfCoreIntegral::open(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let mut file = file;
+        file.seek(SeekFrom::Start(24)).unwrap();
+        file.write_all(&u64::MAX.to_le_bytes()).unwrap();
+        let err = OutOfCoreIntegral::open(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_integral_to_mmap() {
+
+        let cint_data = initialize();
+        let path = std::env::temp_dir().join(format!("rest_libcint_mmap_{}.bin", std::process::id()));
+
+        let shl_slices = [[0, 19], [2, 15], [0, 19]];
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int3c2e_ip1>(Some(&shl_slices));
+        let out = cint_data.integral_s1_to_mmap::<int3c2e_ip1>(&path, Some(&shl_slices)).unwrap();
+        assert_eq!(out.as_slice(), &out_ref);
+        assert_eq!(out.layout().shape, shape_ref);
+        assert_eq!(out.layout().aosym, AoSym::S1);
+        drop(out);
+
+        let (out_ref, shape_ref) = cint_data.integral_s2ij::<int2e>(None);
+        cint_data.integral_s2ij_to_mmap::<int2e>(&path, None).unwrap();
+        let out = MmapIntegral::open(&path).unwrap();
+        assert_eq!(out.as_slice(), &out_ref);
+        assert_eq!(out.layout().shape, shape_ref);
+        assert_eq!(out.layout().aosym, AoSym::S2ij);
+        assert_eq!(out.layout().shl_slices, vec![[0, 19]; 4]);
+        assert!(out.matches(&cint_data));
+
+        // fingerprint changes with basis data
+        let mut cint_data_mod = cint_data.clone();
+        cint_data_mod.set_exp_cutoff(ExpCutoff::Tight);
+        assert!(!out.matches(&cint_data_mod));
+        drop(out);
+
+        // untrusted header: truncated data, and shape whose size overflows
+        // (header words: magic, version, data offset, symmetry, order, fingerprint, n_center = 4, 4 shell slices,
+        // n_dim = 2, shape; first dimension of shape starts at byte 128)
+        use std::io::{Seek, SeekFrom, Write};
+        let mut file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_len(file.metadata().unwrap().len() - 8).unwrap();
+        let err = MmapIntegral::open(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        file.seek(SeekFrom::Start(128)).unwrap();
+        file.write_all(&u64::MAX.to_le_bytes()).unwrap();
+        let err = MmapIntegral::open(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_integral_s1_sparse() {
+
+        let cint_data = initialize();
+        let threshold = 1e-4;
+
+        // 4-center: dense conversion, lookup and contraction
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int2e>(None);
+        let out_ref = Array::from_shape_vec(shape_ref.clone().f(), out_ref).unwrap();
+        let sparse = cint_data.integral_s1_sparse::<int2e>(None, threshold);
+        assert!(sparse.n_blocks() <= sparse.n_shell_tuples());
+        assert_eq!(sparse.shape(), shape_ref);
+        let (dense, shape) = sparse.to_dense();
+        let dense = Array::from_shape_vec(shape.f(), dense).unwrap();
+        assert!((&dense - &out_ref).mapv(f64::abs).iter().all(|&v| v < threshold));
+        for ao in [[0, 0, 0, 0], [3, 17, 42, 5], [42, 42, 42, 42], [11, 7, 30, 29]] {
+            assert_eq!(sparse.get(&ao, 0), dense[ao]);
+        }
+        for (shls, block) in sparse.iter() {
+            assert_eq!(block.shape, shls.iter().map(|&shl| cint_data.cgto_size(shl)).collect_vec());
+        }
+
+        let dm = Array2::from_shape_fn((43, 43), |(i, j)| ((i + j) as f64 * 0.1).cos() * 0.5);
+        let (vj, vj_shape) = sparse.contract_ij(dm.t().iter().cloned().collect_vec().as_slice());
+        let vj_ref = (0..43).cartesian_product(0..43).map(|(k, l)| {
+            (&out_ref.slice(s![.., .., k, l]) * &dm).sum()
+        }).collect_vec();
+        assert_eq!(vj_shape, vec![43, 43]);
+        let vj = Array::from_shape_vec((43, 43).f(), vj).unwrap();
+        let vj_ref = Array::from_shape_vec((43, 43), vj_ref).unwrap();
+        assert_relative_eq!((vj - vj_ref).mapv(f64::abs).sum(), 0., epsilon=1e-3);
+
+        // 3-center with components and slices
+        let shl_slices = [[0, 19], [2, 15], [5, 19]];
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int3c2e_ip1>(Some(&shl_slices));
+        let sparse = cint_data.integral_s1_sparse::<int3c2e_ip1>(Some(&shl_slices), 0.);
+        assert_eq!(sparse.n_blocks(), sparse.n_shell_tuples());
+        let (dense, shape) = sparse.to_dense();
+        assert_eq!(shape, shape_ref);
+        assert_eq!(dense, out_ref);
+    }
+
+    #[test]
+    fn test_for_each_block() {
+
+        use std::sync::Mutex;
+        let cint_data = initialize();
+
+        // s1: assemble dense tensor from visited blocks
+        let shl_slices = [[0, 19], [2, 15], [5, 19]];
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int3c2e_ip1>(Some(&shl_slices));
+        let out_ref = Array::from_shape_vec(shape_ref.clone().f(), out_ref).unwrap();
+        let out = Mutex::new(Array::<f64, _>::zeros(shape_ref.f()));
+        let n_visited = Mutex::new(0);
+        cint_data.for_each_block::<int3c2e_ip1, _>(AoSym::S1, Some(&shl_slices), |shls, ao_offsets, block| {
+            assert_eq!(block.shape, [cint_data.cgto_size(shls[0]), cint_data.cgto_size(shls[1]), cint_data.cgto_size(shls[2]), 3]);
+            let block = ArrayView::from_shape(block.shape.f(), block.data).unwrap();
+            let [i0, j0, k0] = [ao_offsets[0], ao_offsets[1], ao_offsets[2]];
+            let (di, dj, dk) = (block.shape()[0], block.shape()[1], block.shape()[2]);
+            out.lock().unwrap().slice_mut(s![i0..i0+di, j0..j0+dj, k0..k0+dk, ..]).assign(&block);
+            *n_visited.lock().unwrap() += 1;
+        });
+        assert_eq!(*n_visited.lock().unwrap(), 19 * 13 * 14);
+        assert_eq!(out.into_inner().unwrap(), out_ref);
+
+        // s2ij: only shl_i <= shl_j visited
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int2e>(None);
+        let out_ref = Array::from_shape_vec(shape_ref.f(), out_ref).unwrap();
+        let n_visited = Mutex::new(0);
+        cint_data.for_each_block::<int2e, _>(AoSym::S2ij, None, |shls, ao_offsets, block| {
+            assert!(shls[0] <= shls[1]);
+            let block = ArrayView::from_shape(block.shape.f(), block.data).unwrap();
+            let [i0, j0, k0, l0] = [ao_offsets[0], ao_offsets[1], ao_offsets[2], ao_offsets[3]];
+            let (di, dj, dk, dl) = (block.shape()[0], block.shape()[1], block.shape()[2], block.shape()[3]);
+            assert_eq!(block, out_ref.slice(s![i0..i0+di, j0..j0+dj, k0..k0+dk, l0..l0+dl]).into_dyn());
+            *n_visited.lock().unwrap() += 1;
+        });
+        assert_eq!(*n_visited.lock().unwrap(), 19 * 20 / 2 * 19 * 19);
+
+        // visitor with nested parallel integral calls should not deadlock
+        let n_visited = Mutex::new(0);
+        cint_data.for_each_block::<int1e_ovlp, _>(AoSym::S1, None, |shls, _, block| {
+            let shl_slices = [[shls[0], shls[0] + 1], [shls[1], shls[1] + 1]];
+            let (out, _) = cint_data.integral_s1::<int1e_ovlp>(Some(&shl_slices));
+            assert_eq!(out, block.data);
+            *n_visited.lock().unwrap() += 1;
+        });
+        assert_eq!(*n_visited.lock().unwrap(), 19 * 19);
+    }
+
+    #[test]
+    fn test_integral_ao2mo() {
+
+        let cint_data = initialize();
+        let nao = 43;
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int2e>(None);
+        let eri = Array::from_shape_vec(shape_ref.f(), out_ref).unwrap().into_dimensionality::<Ix4>().unwrap();
+
+        let transform = |c: [&Array2<f64>; 4]| {
+            let [ci, cj, ck, cl] = c;
+            let (ni, nj, nk, nl) = (ci.ncols(), cj.ncols(), ck.ncols(), cl.ncols());
+            // (uv|wx) C_up C_vq C_wr C_xs, transformed one index at a time
+            let t = Array4::from_shape_fn((ni, nao, nao, nao).f(), |(p, v, w, x)| (0..nao).map(|u| ci[[u, p]] * eri[[u, v, w, x]]).sum::<f64>());
+            let t = Array4::from_shape_fn((ni, nj, nao, nao).f(), |(p, q, w, x)| (0..nao).map(|v| cj[[v, q]] * t[[p, v, w, x]]).sum::<f64>());
+            let t = Array4::from_shape_fn((ni, nj, nk, nao).f(), |(p, q, r, x)| (0..nao).map(|w| ck[[w, r]] * t[[p, q, w, x]]).sum::<f64>());
+            Array4::from_shape_fn((ni, nj, nk, nl).f(), |(p, q, r, s)| (0..nao).map(|x| cl[[x, s]] * t[[p, q, r, x]]).sum::<f64>())
+        };
+
+        // same coefficients: both pairs packed
+        let c = pseudo_coeff(nao, 6, 0.37);
+        let c_slice = c.as_slice_memory_order().unwrap();
+        let (out, shape) = cint_data.integral_ao2mo([c_slice; 4]);
+        assert_eq!(shape, vec![21, 21]);
+        let out_ref = transform([&c; 4]);
+        let tril = (0..6).flat_map(|q| (0..(q + 1)).map(move |p| (p, q))).collect_vec();
+        for (rs, &(r, s)) in tril.iter().enumerate() {
+            for (pq, &(p, q)) in tril.iter().enumerate() {
+                assert_relative_eq!(out[pq + 21 * rs], out_ref[[p, q, r, s]], max_relative=1e-10, epsilon=1e-12);
+            }
+        }
+
+        // general coefficients: (ij| packed, |kl) full
+        let c_k = pseudo_coeff(nao, 4, 0.61);
+        let c_l = pseudo_coeff(nao, 5, 0.23);
+        let (out, shape) = cint_data.integral_ao2mo([c_slice, c_slice, c_k.as_slice_memory_order().unwrap(), c_l.as_slice_memory_order().unwrap()]);
+        assert_eq!(shape, vec![21, 20]);
+        let out_ref = transform([&c, &c, &c_k, &c_l]);
+        for (s, r) in (0..5).cartesian_product(0..4) {
+            for (pq, &(p, q)) in tril.iter().enumerate() {
+                assert_relative_eq!(out[pq + 21 * (r + 4 * s)], out_ref[[p, q, r, s]], max_relative=1e-10, epsilon=1e-12);
+            }
+        }
+
+        // half transformation: AO pair packed
+        let (half, shape) = cint_data.integral_ao2mo_half(c_k.as_slice_memory_order().unwrap(), c_l.as_slice_memory_order().unwrap());
+        assert_eq!(shape, vec![43 * 44 / 2, 20]);
+        let (u, v) = (5, 17);
+        let (r, s) = (2, 3);
+        let val_ref = (0..nao).cartesian_product(0..nao).map(|(w, x)| eri[[u, v, w, x]] * c_k[[w, r]] * c_l[[x, s]]).sum::<f64>();
+        assert_relative_eq!(half[(u + v * (v + 1) / 2) + 946 * (r + 4 * s)], val_ref, max_relative=1e-10, epsilon=1e-12);
+    }
+
+    #[test]
+    fn test_integral_ri_ao2mo() {
+
+        let cint_data = initialize();
+        let nao = 43;
+        // shells 10..19 are used as auxiliary basis
+        let aux_slice = [10, 19];
+        let shl_slices = [[0, 19], [0, 19], aux_slice];
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int3c2e>(Some(&shl_slices));
+        let naux = shape_ref[2];
+        let eri = Array::from_shape_vec(shape_ref.f(), out_ref).unwrap().into_dimensionality::<Ix3>().unwrap();
+
+        let c_occ = pseudo_coeff(nao, 5, 0.37);
+        let c_vir = pseudo_coeff(nao, 8, 0.61);
+        let transform = |ci: &Array2<f64>, cj: &Array2<f64>| Array3::from_shape_fn((ci.ncols(), cj.ncols(), naux).f(), |(p, q, aux)| {
+            (0..nao).cartesian_product(0..nao).map(|(u, v)| ci[[u, p]] * eri[[u, v, aux]] * cj[[v, q]]).sum::<f64>()
+        });
+
+        // (ia|P), general coefficients, small batches
+        let (out, shape) = cint_data.integral_ri_ao2mo(&shl_slices, c_occ.as_slice_memory_order().unwrap(), c_vir.as_slice_memory_order().unwrap(), false, 8 * nao * nao * 5);
+        assert_eq!(shape, vec![5 * 8, naux]);
+        let out_ref = transform(&c_occ, &c_vir);
+        let out = Array::from_shape_vec((5, 8, naux).f(), out).unwrap();
+        out.iter().zip(out_ref.iter()).for_each(|(&v, &v_ref)| assert_relative_eq!(v, v_ref, max_relative=1e-10, epsilon=1e-12));
+
+        // (pq|P), same coefficients, packed
+        let c = c_occ.as_slice_memory_order().unwrap();
+        let (out_packed, shape) = cint_data.integral_ri_ao2mo(&shl_slices, c, c, false, 1 << 30);
+        assert_eq!(shape, vec![15, naux]);
+        let out_ref = transform(&c_occ, &c_occ);
+        let tril = (0..5).flat_map(|q| (0..(q + 1)).map(move |p| (p, q))).collect_vec();
+        for aux in 0..naux {
+            for (pq, &(p, q)) in tril.iter().enumerate() {
+                assert_relative_eq!(out_packed[pq + 15 * aux], out_ref[[p, q, aux]], max_relative=1e-10, epsilon=1e-12);
+            }
+        }
+
+        // (pq|P), same coefficients of different shell slices (the two H atoms), not packed
+        let shl_slices_hh = [[11, 15], [15, 19], aux_slice];
+        let (out_ref, shape_ref) = cint_data.integral_s1::<int3c2e>(Some(&shl_slices_hh));
+        let eri_hh = Array::from_shape_vec(shape_ref.f(), out_ref).unwrap().into_dimensionality::<Ix3>().unwrap();
+        let c_h = pseudo_coeff(6, 4, 0.37);
+        let c = c_h.as_slice_memory_order().unwrap();
+        let (out, shape) = cint_data.integral_ri_ao2mo(&shl_slices_hh, c, c, false, 1 << 30);
+        assert_eq!(shape, vec![4 * 4, naux]);
+        let out = Array::from_shape_vec((4, 4, naux).f(), out).unwrap();
+        let out_ref = Array3::from_shape_fn((4, 4, naux).f(), |(p, q, aux)| {
+            (0..6).cartesian_product(0..6).map(|(u, v)| c_h[[u, p]] * eri_hh[[u, v, aux]] * c_h[[v, q]]).sum::<f64>()
+        });
+        out.iter().zip(out_ref.iter()).for_each(|(&v, &v_ref)| assert_relative_eq!(v, v_ref, max_relative=1e-10, epsilon=1e-12));
+        assert!((&out - &out.view().permuted_axes([1, 0, 2])).mapv(f64::abs).sum() > 1e-6);
+
+        // metric: J = L L^T with L lower-triangular, and B L^T = (pq|P), also with metric applied in small batches
+        let (chol, shape) = cint_data.integral_ri_metric_cholesky(aux_slice);
+        assert_eq!(shape, vec![naux, naux]);
+        let chol = Array::from_shape_vec((naux, naux).f(), chol).unwrap();
+        let (j2c, _) = cint_data.integral_s1::<int2c2e>(Some(&[aux_slice, aux_slice]));
+        let j2c = Array::from_shape_vec((naux, naux).f(), j2c).unwrap();
+        chol.indexed_iter().filter(|((p, q), _)| p < q).for_each(|(_, &v)| assert_eq!(v, 0.));
+        chol.dot(&chol.t()).iter().zip(j2c.iter()).for_each(|(&v, &v_ref)| assert_relative_eq!(v, v_ref, max_relative=1e-10, epsilon=1e-12));
+
+        let c = c_occ.as_slice_memory_order().unwrap();
+        let out_packed = Array::from_shape_vec((15, naux).f(), out_packed).unwrap();
+        for max_memory in [1 << 30, 8 * nao * nao * 5] {
+            let (out, _) = cint_data.integral_ri_ao2mo(&shl_slices, c, c, true, max_memory);
+            let out = Array::from_shape_vec((15, naux).f(), out).unwrap();
+            out.dot(&chol.t()).iter().zip(out_packed.iter()).for_each(|(&v, &v_ref)| assert_abs_diff_eq!(v, v_ref, epsilon=1e-10));
+        }
+    }
+
+    #[test]
+    fn test_hcore() {
+
+        let mut cint_data = initialize();
+        // reference of `int1e_kin + int1e_nuc`, see `pyscf_integral_h2o_tzvp.ipynb`
+        for (cint_type, ref_sum, ref_scaled) in [
+            (CintType::Spheric, -819.1634497556199, 257.55323613066383),
+            (CintType::Cartesian, -1908.2936644666665, 471.6445926513449),
+        ] {
+            cint_data.set_cint_type(&cint_type);
+            let (out, shape) = cint_data.hcore();
+            let (kin, shape_ref) = cint_data.integral_s1::<int1e_kin>(None);
+            let (nuc, _) = cint_data.integral_s1::<int1e_nuc>(None);
+            assert_eq!(shape, shape_ref);
+            let scale = Array::linspace(-1., 1., out.len());
+            let hcore = Array::from_vec(out.clone());
+            assert_relative_eq!(hcore.sum(), ref_sum, max_relative=1e-10);
+            assert_relative_eq!((hcore * scale).sum(), ref_scaled, max_relative=1e-10);
+            itertools::izip!(out, kin, nuc).for_each(|(v, t, n)| assert_relative_eq!(v, t + n, max_relative=1e-12, epsilon=1e-12));
+        }
+
+        cint_data.set_cint_type(&CintType::Spheric);
+        let (out, shape) = cint_data.hcore_spinor();
+        let (kin, shape_ref) = cint_data.integral_spinor_s1::<int1e_kin>(None);
+        let (nuc, _) = cint_data.integral_spinor_s1::<int1e_nuc>(None);
+        assert_eq!(shape, shape_ref);
+        itertools::izip!(out, kin, nuc).for_each(|(v, t, n)| assert_relative_eq!((v - t - n).norm(), 0., epsilon=1e-10));
+    }
+
+    #[test]
+    fn test_nuclear_repulsion() {
+
+        let cint_data = initialize();
+        let coords = cint_data.atom_coords();
+        let charges = cint_data.atom_charges();
+        let energy = |coords: &[[f64; 3]]| (0..3).cartesian_product(0..3).filter(|(a, b)| b < a).map(|(a, b)| {
+            let dist = (0..3).map(|t| (coords[a][t] - coords[b][t]).powi(2)).sum::<f64>().sqrt();
+            charges[a] * charges[b] / dist
+        }).sum::<f64>();
+        let displaced = |moves: &[(usize, usize, f64)]| {
+            let mut coords = coords.clone();
+            moves.iter().for_each(|&(a, t, h)| coords[a][t] += h);
+            energy(&coords)
+        };
+        assert_relative_eq!(cint_data.nuclear_repulsion_energy(), energy(&coords), max_relative=1e-14);
+
+        // gradient: finite difference, and translational invariance
+        let h = 1e-4;
+        let (grad, shape) = cint_data.nuclear_repulsion_gradient();
+        assert_eq!(shape, vec![3, 3]);
+        for (a, t) in (0..3).cartesian_product(0..3) {
+            let grad_fd = (displaced(&[(a, t, h)]) - displaced(&[(a, t, -h)])) / (2. * h);
+            assert_relative_eq!(grad[t + 3 * a], grad_fd, max_relative=1e-6, epsilon=1e-8);
+        }
+        (0..3).for_each(|t| assert_abs_diff_eq!((0..3).map(|a| grad[t + 3 * a]).sum::<f64>(), 0., epsilon=1e-12));
+
+        // hessian: finite difference
+        let (hess, shape) = cint_data.nuclear_repulsion_hessian();
+        assert_eq!(shape, vec![3, 3, 3, 3]);
+        for ((a, t), (b, u)) in (0..3).cartesian_product(0..3).cartesian_product((0..3).cartesian_product(0..3)) {
+            let hess_fd = (displaced(&[(a, t, h), (b, u, h)]) - displaced(&[(a, t, h), (b, u, -h)])
+                - displaced(&[(a, t, -h), (b, u, h)]) + displaced(&[(a, t, -h), (b, u, -h)])) / (4. * h * h);
+            assert_relative_eq!(hess[(t + 3 * a) + 9 * (u + 3 * b)], hess_fd, max_relative=1e-4, epsilon=1e-6);
+        }
+    }
+
+    #[test]
+    fn test_nuclear_repulsion_ghost_frac() {
+
+        // reference: sum of Z_A Z_B / R_AB from coordinates of the molecule below (9.36 Hartree)
+        let energy_ref = 9.363261243324963;
+        assert_relative_eq!(initialize().nuclear_repulsion_energy(), energy_ref, max_relative=1e-12);
+
+        // ghost atom (zero charge) on top of O, sharing coordinates and nuclear model of O
+        let cint_data = initialize_with(|c_atm, _| c_atm.push(vec![0, 20, 1, 23, 0, 0]));
+        assert_eq!(cint_data.atom_charges(), vec![8., 1., 1., 0.]);
+        assert_relative_eq!(cint_data.nuclear_repulsion_energy(), energy_ref, max_relative=1e-12);
+        let (grad, shape) = cint_data.nuclear_repulsion_gradient();
+        assert_eq!(shape, vec![3, 4]);
+        assert!(grad.iter().all(|v| v.is_finite()));
+        (0..3).for_each(|t| assert_eq!(grad[t + 3 * 3], 0.));
+        let (hess, _) = cint_data.nuclear_repulsion_hessian();
+        assert!(hess.iter().all(|v| v.is_finite()));
+
+        // fractional charge 0.5 of the second H by `PTR_FRAC_CHARGE`
+        let cint_data = initialize_with(|c_atm, c_env| {
+            c_atm[2][4] = c_env.len() as i32;
+            c_env.push(0.5);
+        });
+        assert_eq!(cint_data.atom_charges(), vec![8., 1., 0.5]);
+        assert_relative_eq!(cint_data.nuclear_repulsion_energy(), 6.933448540470993, max_relative=1e-12);
+    }
+
+    #[test]
+    fn test_grad1e_assembly() {
+
+        let cint_data = initialize();
+        let nao = 43;
+        // AO ranges of atoms: O (0..31), H (31..37), H (37..43)
+        let aoslices = [0..31, 31..37, 37..43];
+        let dm = Array2::from_shape_fn((nao, nao).f(), |(i, j)| ((i * 5 + j * 11) as f64 * 0.29).cos());
+
+        // overlap derivative from int1e_ipovlp
+        let (s1, shape) = cint_data.integral_s1::<int1e_ipovlp>(None);
+        let s1 = Array::from_shape_vec(shape.f(), s1).unwrap().into_dimensionality::<Ix3>().unwrap();
+        let mut ovlp_derivs = vec![];
+        for atm_id in 0..3 {
+            let (out, shape) = cint_data.ovlp_deriv(atm_id);
+            assert_eq!(shape, vec![nao, nao, 3]);
+            let out = Array::from_shape_vec((nao, nao, 3).f(), out).unwrap();
+            let mut out_ref = Array3::<f64>::zeros((nao, nao, 3).f());
+            let range = aoslices[atm_id].clone();
+            out_ref.slice_mut(s![range.clone(), .., ..]).scaled_add(-1., &s1.slice(s![range.clone(), .., ..]));
+            let s1_t = s1.clone().permuted_axes([1, 0, 2]);
+            out_ref.slice_mut(s![.., range.clone(), ..]).scaled_add(-1., &s1_t.slice(s![.., range, ..]));
+            out.iter().zip(out_ref.iter()).for_each(|(&v, &v_ref)| assert_abs_diff_eq!(v, v_ref, epsilon=1e-14));
+            ovlp_derivs.push(out);
+        }
+
+        // hcore derivative: symmetric, and translational invariance
+        let hcore_deriv = cint_data.hcore_generator();
+        let hcore_derivs = (0..3).map(|atm_id| {
+            let (out, _) = hcore_deriv(atm_id);
+            Array::from_shape_vec((nao, nao, 3).f(), out).unwrap()
+        }).collect_vec();
+        let (out, _) = cint_data.hcore_deriv(1);
+        assert_eq!(Array::from_shape_vec((nao, nao, 3).f(), out).unwrap(), hcore_derivs[1]);
+        let h_sum = &hcore_derivs[0] + &hcore_derivs[1] + &hcore_derivs[2];
+        h_sum.iter().for_each(|&v| assert_abs_diff_eq!(v, 0., epsilon=1e-8));
+        let h_t = hcore_derivs[1].clone().permuted_axes([1, 0, 2]);
+        hcore_derivs[1].iter().zip(h_t.iter()).for_each(|(&v, &v_t)| assert_abs_diff_eq!(v, v_t, epsilon=1e-12));
+
+        // finite difference of hcore by displacing one atom
+        let h = 1e-4;
+        for (atm_id, t) in [(0, 2), (1, 0), (2, 1)] {
+            let (hcore_p, _) = initialize_with_coord_shift(atm_id, t, h).hcore();
+            let (hcore_m, _) = initialize_with_coord_shift(atm_id, t, -h).hcore();
+            for (i, j) in [(0, 0), (3, 32), (32, 32), (40, 5), (38, 33)] {
+                let fd = (hcore_p[i + nao * j] - hcore_m[i + nao * j]) / (2. * h);
+                assert_abs_diff_eq!(hcore_derivs[atm_id][[i, j, t]], fd, epsilon=1e-6);
+            }
+        }
+
+        // contraction with (non-symmetric) density matrix
+        let dm_slice = dm.as_slice_memory_order().unwrap();
+        let (grad_h, shape) = cint_data.contract_hcore_deriv(dm_slice);
+        assert_eq!(shape, vec![3, 3]);
+        let (grad_s, _) = cint_data.contract_ovlp_deriv(dm_slice);
+        for (atm_id, t) in (0..3).cartesian_product(0..3) {
+            let grad_h_ref = (&hcore_derivs[atm_id].index_axis(Axis(2), t) * &dm).sum();
+            let grad_s_ref = (&ovlp_derivs[atm_id].index_axis(Axis(2), t) * &dm).sum();
+            assert_relative_eq!(grad_h[t + 3 * atm_id], grad_h_ref, max_relative=1e-10, epsilon=1e-10);
+            assert_relative_eq!(grad_s[t + 3 * atm_id], grad_s_ref, max_relative=1e-10, epsilon=1e-10);
+        }
+    }
+
+    #[test]
+    fn test_grad_jk() {
+
+        let cint_data = initialize();
+        let nao = 43;
+        let aoslices = [0..31, 31..37, 37..43];
+        // symmetric density matrices (memory of symmetric matrix is the same for c- and f-order)
+        let dm_of = |nocc: usize, seed: f64| {
+            let c = pseudo_coeff(nao, nocc, seed) * 0.3;
+            c.dot(&c.t())
+        };
+        let dms = [dm_of(5, 0.37), dm_of(4, 0.61)];
+
+        // dense reference from int2e_ip1, f-contiguous (i, j, k, l, 3)
+        let (ip1, shape) = cint_data.integral_s1::<int2e_ip1>(None);
+        let ip1 = Array::from_shape_vec(shape.f(), ip1).unwrap().into_dimensionality::<Ix5>().unwrap();
+        let grad_ref = |dm: &Array2<f64>| {
+            let mut grad_j = Array2::<f64>::zeros((3, 3).f());
+            let mut grad_k = Array2::<f64>::zeros((3, 3).f());
+            for t in 0..3 {
+                let eri = ip1.index_axis(Axis(4), t);
+                for (atm_id, range) in aoslices.iter().enumerate() {
+                    for i in range.clone() {
+                        for j in 0..nao {
+                            let (mut vj, mut vk) = (0., 0.);
+                            for (k, l) in (0..nao).cartesian_product(0..nao) {
+                                let v = eri[[i, j, k, l]];
+                                vj += v * dm[[k, l]];
+                                vk += v * dm[[i, k]] * dm[[j, l]];
+                            }
+                            grad_j[[t, atm_id]] -= 4. * dm[[i, j]] * vj;
+                            grad_k[[t, atm_id]] -= 4. * vk;
+                        }
+                    }
+                }
+            }
+            (grad_j, grad_k)
+        };
+
+        let dm_slices = dms.iter().map(|dm| dm.as_slice_memory_order().unwrap()).collect_vec();
+        let (grad_j, grad_k, shape) = cint_data.grad_jk(&dm_slices, true, true, 0.);
+        assert_eq!(shape, vec![3, 3, 2]);
+        for (n, dm) in dms.iter().enumerate() {
+            let (grad_j_ref, grad_k_ref) = grad_ref(dm);
+            for (atm_id, t) in (0..3).cartesian_product(0..3) {
+                assert_relative_eq!(grad_j[t + 3 * atm_id + 9 * n], grad_j_ref[[t, atm_id]], max_relative=1e-9, epsilon=1e-10);
+                assert_relative_eq!(grad_k[t + 3 * atm_id + 9 * n], grad_k_ref[[t, atm_id]], max_relative=1e-9, epsilon=1e-10);
+            }
+            // translational invariance
+            for t in 0..3 {
+                assert_abs_diff_eq!((0..3).map(|a| grad_j[t + 3 * a + 9 * n]).sum::<f64>(), 0., epsilon=1e-8);
+                assert_abs_diff_eq!((0..3).map(|a| grad_k[t + 3 * a + 9 * n]).sum::<f64>(), 0., epsilon=1e-8);
+            }
+        }
+
+        // screening only changes result within threshold; exchange only
+        let (grad_j_screen, grad_k_screen, _) = cint_data.grad_jk(&dm_slices[..1], false, true, 1e-12);
+        assert!(grad_j_screen.is_empty());
+        (0..9).for_each(|idx| assert_abs_diff_eq!(grad_k_screen[idx], grad_k[idx], epsilon=1e-8));
+
+        // threshold large enough to actually skip quartets (result is not bitwise the same), still accurate
+        let (grad_j_screen, grad_k_screen, _) = cint_data.grad_jk(&dm_slices, true, true, 1e-7);
+        assert!(grad_j_screen != grad_j && grad_k_screen != grad_k);
+        (0..18).for_each(|idx| assert_abs_diff_eq!(grad_j_screen[idx], grad_j[idx], epsilon=1e-5));
+        (0..18).for_each(|idx| assert_abs_diff_eq!(grad_k_screen[idx], grad_k[idx], epsilon=1e-5));
+
+        // every quartet skipped
+        let (grad_j_screen, grad_k_screen, _) = cint_data.grad_jk(&dm_slices, true, true, 1e10);
+        assert!(grad_j_screen.iter().chain(&grad_k_screen).all(|&v| v == 0.));
+
+        // Schwarz factors are symmetric
+        let q_cond = cint_data.schwarz_shell_pairs();
+        assert_eq!(q_cond.len(), 19 * 19);
+        (0..19).cartesian_product(0..19).for_each(|(i, j)| assert_eq!(q_cond[i + 19 * j], q_cond[j + 19 * i]));
+
+        // Schwarz bound of derivative integrals: |(∇i j|kl)| <= Q'_ij Q_kl
+        let q_cond_ip1 = cint_data.schwarz_shell_pairs_ip1();
+        let cgto_loc = cint_data.cgto_loc();
+        let shl_of = (0..nao).map(|u| (0..19).find(|&shl| cgto_loc[shl + 1] > u).unwrap()).collect_vec();
+        ip1.indexed_iter().for_each(|((i, j, k, l, _), &v)| {
+            let bound = q_cond_ip1[shl_of[i] + 19 * shl_of[j]] * q_cond[shl_of[k] + 19 * shl_of[l]];
+            assert!(v.abs() <= bound * (1. + 1e-10) + 1e-14);
+        });
+    }
+
+    #[test]
+    fn test_fcidump() {
+
+        let cint_data = initialize();
+        let nao = 43;
+        let coeff = pseudo_coeff(nao, 8, 0.37);
+        let coeff_slice = coeff.as_slice_memory_order().unwrap();
+
+        // reference: core Hamiltonian and MO integrals of all 8 orbitals
+        let (kin, _) = cint_data.integral_s1::<int1e_kin>(None);
+        let (nuc, _) = cint_data.integral_s1::<int1e_nuc>(None);
+        let hcore = Array::from_shape_vec((nao, nao).f(), kin).unwrap() + Array::from_shape_vec((nao, nao).f(), nuc).unwrap();
+        let h_mo = coeff.t().dot(&hcore).dot(&coeff);
+        let (eri, _) = cint_data.integral_ao2mo([coeff_slice; 4]);
+        let pair = |p: usize, q: usize| if p <= q { p + q * (q + 1) / 2 } else { q + p * (p + 1) / 2 };
+        let eri_mo = |p: usize, q: usize, r: usize, s: usize| eri[pair(p, q) + 36 * pair(r, s)];
+        let coords = cint_data.atom_coords();
+        let charges = cint_data.atom_charges();
+        let enuc = (0..3).cartesian_product(0..3).filter(|(a, b)| a < b).map(|(a, b)| {
+            let dist = (0..3).map(|t| (coords[a][t] - coords[b][t]).powi(2)).sum::<f64>().sqrt();
+            charges[a] * charges[b] / dist
+        }).sum::<f64>();
+
+        // no frozen core
+        let fcidump = cint_data.fcidump(coeff_slice, 0, 8, 6);
+        assert_eq!((fcidump.norb, fcidump.nelec, fcidump.ms2), (8, 6, 0));
+        assert_relative_eq!(fcidump.ecore, enuc, max_relative=1e-12);
+        assert_relative_eq!(fcidump.h1e[pair(2, 5)], h_mo[[2, 5]], max_relative=1e-10);
+        assert_eq!(fcidump.h2e.len(), 36 * 36);
+        assert_relative_eq!(fcidump.h2e[pair(1, 4) + 36 * pair(3, 3)], eri_mo(1, 4, 3, 3), max_relative=1e-12);
+
+        // two frozen core orbitals folded
+        let fcidump = cint_data.fcidump(coeff_slice, 2, 6, 4);
+        let ecore_ref = enuc + (0..2).map(|c| 2. * h_mo[[c, c]]).sum::<f64>()
+            + (0..2).cartesian_product(0..2).map(|(c, d)| 2. * eri_mo(c, c, d, d) - eri_mo(c, d, d, c)).sum::<f64>();
+        assert_relative_eq!(fcidump.ecore, ecore_ref, max_relative=1e-10);
+        let (p, q) = (1, 4);
+        let h_ref = h_mo[[p + 2, q + 2]] + (0..2).map(|c| 2. * eri_mo(p + 2, q + 2, c, c) - eri_mo(p + 2, c, c, q + 2)).sum::<f64>();
+        assert_relative_eq!(fcidump.h1e[pair(p, q)], h_ref, max_relative=1e-10);
+        assert_relative_eq!(fcidump.h2e[pair(0, 5) + 21 * pair(2, 3)], eri_mo(2, 7, 4, 5), max_relative=1e-12);
+
+        // written file: header, and s8-unique integrals with 1-based indices
+        let mut fcidump = fcidump;
+        fcidump.orbsym = Some(vec![1, 1, 2, 3, 1, 4]);
+        let mut buf = Vec::new();
+        fcidump.write_to(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines = text.lines().collect_vec();
+        assert_eq!(lines[0], " &FCI NORB=6,NELEC=4,MS2=0,");
+        assert_eq!(lines[1], "  ORBSYM=1,1,2,3,1,4,");
+        assert_eq!(lines[3], " &END");
+        let records = lines[4..].iter().map(|line| {
+            let tokens = line.split_whitespace().collect_vec();
+            let v = tokens[0].parse::<f64>().unwrap();
+            let idx = tokens[1..].iter().map(|t| t.parse::<usize>().unwrap()).collect_vec();
+            (v, idx)
+        }).collect_vec();
+        assert!(records.len() <= 21 * 22 / 2 + 21 + 1);
+        let (v, idx) = records.last().unwrap();
+        assert_eq!(idx, &vec![0, 0, 0, 0]);
+        assert_relative_eq!(*v, fcidump.ecore, max_relative=1e-15);
+        let (v, _) = records.iter().find(|(_, idx)| idx == &vec![6, 1, 4, 3]).unwrap();
+        assert_relative_eq!(*v, eri_mo(2, 7, 4, 5), max_relative=1e-15);
+        let (v, _) = records.iter().find(|(_, idx)| idx == &vec![5, 2, 0, 0]).unwrap();
+        assert_relative_eq!(*v, fcidump.h1e[pair(1, 4)], max_relative=1e-15);
+    }
+
+    #[test]
+    fn test_estimate() {
+
+        let cint_data = initialize();
+
+        let est = cint_data.estimate::<int1e_ipovlp>(AoSym::S1, None);
+        assert_eq!(est.out_bytes, 43 * 43 * 3 * 8);
+        assert_eq!(est.n_shell_tuples, 19 * 19);
+        assert_eq!(est.buffer_bytes, 0);
+        assert!(est.cache_bytes > 0);
+        let flops_ref = (0..19).cartesian_product(0..19).map(|(i, j)| cint_data.shell_tuple_cost(&[i, j])).sum::<f64>() * 3.;
+        assert_relative_eq!(est.flops, flops_ref, max_relative=1e-12);
+
+        let est = cint_data.estimate::<int2e>(AoSym::S2ij, None);
+        assert_eq!(est.out_bytes, 43 * 44 / 2 * 43 * 43 * 8);
+        assert_eq!(est.n_shell_tuples, 19 * 20 / 2 * 19 * 19);
+        assert!(est.buffer_bytes > 0);
+        let flops_ref = (0..19).cartesian_product(0..19).cartesian_product(0..19).cartesian_product(0..19)
+            .filter(|&(((i, j), _), _)| i <= j)
+            .map(|(((i, j), k), l)| cint_data.shell_tuple_cost(&[i, j, k, l])).sum::<f64>();
+        assert_relative_eq!(est.flops, flops_ref, max_relative=1e-12);
+
+        // slices
+        let shl_slices = [[0, 5], [3, 11], [2, 19]];
+        let est = cint_data.estimate::<int3c2e>(AoSym::S1, Some(&shl_slices));
+        let (out, _) = cint_data.integral_s1::<int3c2e>(Some(&shl_slices));
+        assert_eq!(est.out_bytes, out.len() * 8);
+        assert_eq!(est.n_shell_tuples, 5 * 8 * 17);
+    }
+
+    #[test]
+    fn test_shell_tuple_cost() {
+
+        let cint_data = initialize();
+        let nbas = 19;
+        let costs = (0..nbas).map(|shl| cint_data.shell_tuple_cost(&[shl])).collect_vec();
+        assert!(costs.iter().all(|&c| c > 0.));
+        // shell with most functions and primitives is the most expensive one in pairs
+        let shl_max = (0..nbas).max_by(|&a, &b| costs[a as usize].total_cmp(&costs[b as usize])).unwrap();
+        let pair_max = (0..nbas).cartesian_product(0..nbas)
+            .max_by(|&(a, b), &(c, d)| cint_data.shell_tuple_cost(&[a, b]).total_cmp(&cint_data.shell_tuple_cost(&[c, d])))
+            .unwrap();
+        assert_eq!(pair_max, (shl_max, shl_max));
+        assert_eq!(cint_data.shell_tuple_cost(&[3, 11]), cint_data.shell_tuple_cost(&[11, 3]));
+    }
+
+    #[test]
+    fn test_parallelism() {
+
+        let mut cint_data = initialize();
+        let (int2e_ref, _) = cint_data.integral_s2ij::<int2e>(None);
+        let (ipovlp_ref, _) = cint_data.integral_s1::<int1e_ipovlp>(None);
+
+        // sequential evaluation
+        cint_data.set_parallelism(Parallelism::Sequential);
+        assert_eq!(cint_data.integral_s2ij::<int2e>(None).0, int2e_ref);
+        assert_eq!(cint_data.integral_s1::<int1e_ipovlp>(None).0, ipovlp_ref);
+
+        // caller-supplied pool
+        let pool = std::sync::Arc::new(rayon::ThreadPoolBuilder::new().num_threads(3).build().unwrap());
+        cint_data.set_parallelism(Parallelism::Pool(pool));
+        assert_eq!(cint_data.integral_s2ij::<int2e>(None).0, int2e_ref);
+
+        // nested inside parallel loop of another pool, whose size differs from the pool of integrals
+        let outer = rayon::ThreadPoolBuilder::new().num_threads(7).build().unwrap();
+        let results = outer.install(|| {
+            use rayon::prelude::*;
+            (0..4).into_par_iter().map(|_| cint_data.integral_s1::<int1e_ipovlp>(None).0).collect::<Vec<_>>()
+        });
+        results.iter().for_each(|out| assert_eq!(out, &ipovlp_ref));
+    }
+
+    #[test]
+    fn test_optimizer_cache() {
+
+        let mut cint_data = initialize();
+        let shl_slices = vec![[0, 3], [0, 3], [0, 3], [0, 3]];
+        let (out_full, _) = cint_data.integral_s1::<int2e>(None);
+        let (out_slice, _) = cint_data.integral_s1::<int2e>(Some(&shl_slices));
+        assert_eq!(cint_data.optimizer_cache_len(), 1);
+        let opt = cint_data.cached_optimizer::<int2e>();
+        assert!(std::sync::Arc::ptr_eq(&opt, &cint_data.cached_optimizer::<int2e>()));
+
+        // copies share optimizer cache
+        let (out_clone, _) = cint_data.clone().integral_s1::<int2e>(None);
+        assert_eq!(out_clone, out_full);
+        assert!(std::sync::Arc::ptr_eq(&opt, &cint_data.cached_optimizer::<int2e>()));
+        let _ = cint_data.integral_s1::<int1e_ovlp>(None);
+        assert_eq!(cint_data.optimizer_cache_len(), 2);
+
+        // optimizer is regenerated when `c_env` changes
+        cint_data.set_exp_cutoff(ExpCutoff::Loose);
+        assert!(!std::sync::Arc::ptr_eq(&opt, &cint_data.cached_optimizer::<int2e>()));
+        cint_data.set_exp_cutoff(ExpCutoff::Default);
+        cint_data.clear_optimizer_cache();
+        assert_eq!(cint_data.optimizer_cache_len(), 0);
+        let (out_slice_new, _) = cint_data.integral_s1::<int2e>(Some(&shl_slices));
+        assert_eq!(out_slice_new, out_slice);
+    }
+
+    #[test]
+    fn test_clone_with_optimizer() {
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CINTR2CDATA>();
+
+        // optimizer is owned by one copy only; dropping either copy should not affect the other
+        let mut cint_data = initialize();
+        cint_data.optimizer::<int1e_kin>();
+        let cint_clone = cint_data.clone();
+        let (kin_ref, _) = cint_data.integral_s1::<int1e_kin>(None);
+        drop(cint_data);
+        let (kin, _) = cint_clone.integral_s1::<int1e_kin>(None);
+        assert_eq!(kin, kin_ref);
+
+        let mut cint_clone = cint_clone;
+        cint_clone.optimizer::<int1e_kin>();
+        cint_clone.optimizer_destruct();
+        cint_clone.optimizer_destruct();
+    }
+
+    #[test]
+    fn test_multipole() {
+
+        let cint_data = initialize();
+        let origin = MultipoleOrigin::Coord([0.3, -0.2, 1.1]);
+
+        // spherical dipole is (y, z, x)
+        let (cart, cart_shape) = cint_data.integral_multipole_cart(1, &origin);
+        let (sph, sph_shape) = cint_data.integral_multipole_sph(1, &origin);
+        assert_eq!(cart_shape, vec![43, 43, 3]);
+        assert_eq!(sph_shape, vec![43, 43, 3]);
+        let cart = Array::from_shape_vec(cart_shape.f(), cart).unwrap().into_dimensionality::<Ix3>().unwrap();
+        let sph = Array::from_shape_vec(sph_shape.f(), sph).unwrap().into_dimensionality::<Ix3>().unwrap();
+        for (m, t) in [1, 2, 0].into_iter().enumerate() {
+            assert_relative_eq!(
+                (&sph.index_axis(Axis(2), m) - &cart.index_axis(Axis(2), t)).mapv(f64::abs).sum(), 0., epsilon=1e-10);
+        }
+
+        // spherical quadrupole m = 0 is zz - (xx + yy) / 2
+        let (cart, cart_shape) = cint_data.integral_multipole_cart(2, &origin);
+        let (sph, sph_shape) = cint_data.integral_multipole_sph(2, &origin);
+        assert_eq!(cart_shape, vec![43, 43, 6]);
+        assert_eq!(sph_shape, vec![43, 43, 5]);
+        let cart = Array::from_shape_vec(cart_shape.f(), cart).unwrap().into_dimensionality::<Ix3>().unwrap();
+        let sph = Array::from_shape_vec(sph_shape.f(), sph).unwrap().into_dimensionality::<Ix3>().unwrap();
+        let q20 = &cart.index_axis(Axis(2), 5) - 0.5 * (&cart.index_axis(Axis(2), 0) + &cart.index_axis(Axis(2), 3));
+        assert_relative_eq!(
+            (&sph.index_axis(Axis(2), 2) - q20).mapv(f64::abs).sum(), 0., epsilon=1e-10);
+        assert_eq!(cint_data.get_common_origin(), [0., 0., 0.]);
+
+        // octupole and hexadecapole (`int1e_rrr`, `int1e_rrrr`), see `pyscf_integral_h2o_tzvp.ipynb`
+        for (order, ref_sum, ref_scaled) in [
+            (3, 392.38055185121397, -145.93591714799916),
+            (4, 1320.3224493065677, -183.58463835758963),
+        ] {
+            let (out, _) = cint_data.integral_multipole_cart(order, &origin);
+            let scale = Array::linspace(-1., 1., out.len());
+            let out = Array::from_vec(out);
+            assert_relative_eq!(out.sum(), ref_sum, max_relative=1e-10);
+            assert_relative_eq!((out * scale).sum(), ref_scaled, max_relative=1e-10);
+        }
+    }
+
+    #[test]
+    fn test_multipole_expectation() {
+
+        let cint_data = initialize();
+        let scale = Array::linspace(-0.1, 0.1, 43 * 43).into_shape((43, 43)).unwrap();
+        let dm = (&scale + &scale.t()).into_raw_vec();
+        let (ovlp, _) = cint_data.clone().integral_s1::<int1e_ovlp>(None);
+
+        // monopole is total charge
+        let q = cint_data.multipole_cart_expectation(0, &MultipoleOrigin::ChargeCenter, &dm);
+        let n_elec = ovlp.iter().zip(&dm).map(|(s, d)| s * d).sum::<f64>();
+        assert_relative_eq!(q[0], 10. - n_elec, max_relative=1e-10);
+
+        // nuclear dipole vanishes at center of charge
+        let zeros = vec![0.; 43 * 43];
+        let dip = cint_data.multipole_cart_expectation(1, &MultipoleOrigin::ChargeCenter, &zeros);
+        dip.iter().for_each(|&v| assert_relative_eq!(v, 0., epsilon=1e-12));
+
+        // dipole of neutral density is independent to origin
+        let dm_neutral = dm.iter().map(|d| d * 10. / n_elec).collect_vec();
+        let dip0 = cint_data.multipole_cart_expectation(1, &MultipoleOrigin::Coord([0., 0., 0.]), &dm_neutral);
+        let dip1 = cint_data.multipole_cart_expectation(1, &MultipoleOrigin::MassCenter(vec![15.995, 1.008, 1.008]), &dm_neutral);
+        dip0.iter().zip(&dip1).for_each(|(&a, &b)| assert_relative_eq!(a, b, epsilon=1e-8));
+        let dip_sph = cint_data.multipole_sph_expectation(1, &MultipoleOrigin::Coord([0., 0., 0.]), &dm_neutral);
+        assert_relative_eq!(dip_sph[2], dip0[0], epsilon=1e-10);
+    }
+
     fn initialize() -> CINTR2CDATA {
+        initialize_with_coord_shift(0, 0, 0.)
+    }
+
+    /// Deterministic pseudo-coefficients, f-contiguous `(nao, nmo)`; not orthonormal.
+    fn pseudo_coeff(nao: usize, nmo: usize, seed: f64) -> Array2<f64> {
+        Array2::from_shape_fn((nao, nmo).f(), |(u, p)| ((u * 7 + p * 13) as f64 * seed).sin())
+    }
+
+    /// Molecule with coordinate `t` of atom `atm_id` shifted by `h` (Bohr), for finite difference.
+    fn initialize_with_coord_shift(atm_id: usize, t: usize, h: f64) -> CINTR2CDATA {
+        initialize_with(|c_atm, c_env| c_env[c_atm[atm_id][1] as usize + t] += h)
+    }
+
+    /// Molecule with `c_atm` and `c_env` modified by `modify` before building `CINTR2CDATA`.
+    fn initialize_with(modify: impl FnOnce(&mut Vec<Vec<i32>>, &mut Vec<f64>)) -> CINTR2CDATA {
         // mol = gto.Mole(atom="O; H 1 0.94; H 1 0.94 2 104.5", basis="def2-TZVP").build()
         let c_atm = vec![
             [ 8, 20,  1, 23,  0,  0],
@@ -286,7 +1286,10 @@ mod valid_integral_h2o_tzvp {
             1.4279999999999999e+00,  4.3969226782656516e+00
         ];
 
-        let c_atm = c_atm.iter().map(|&v| v.to_vec()).collect_vec();
+        let mut c_env = c_env;
+        let mut c_atm = c_atm.iter().map(|&v| v.to_vec()).collect_vec();
+        modify(&mut c_atm, &mut c_env);
+
         let c_bas = c_bas.iter().map(|&v| v.to_vec()).collect_vec();
         let mut cint_data = CINTR2CDATA::new();
         cint_data.initial_r2c(&c_atm, c_atm.len() as i32, &c_bas, c_bas.len() as i32, &c_env);